@@ -1,5 +1,10 @@
 use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Debug, Serialize)]
@@ -9,12 +14,100 @@ struct OpenRouterRequest {
     temperature: f32,
     max_tokens: Option<u32>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ToolDefinition>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct OpenRouterMessage {
     role: String,
-    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCallRequest>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl OpenRouterMessage {
+    fn system(content: &str) -> Self {
+        Self::plain("system", content)
+    }
+
+    fn user(content: &str) -> Self {
+        Self::plain("user", content)
+    }
+
+    fn plain(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    fn tool_result(tool_call_id: &str, content: &str) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: Some(tool_call_id.to_string()),
+        }
+    }
+
+    fn assistant_tool_calls(tool_calls: Vec<ToolCallRequest>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: None,
+            tool_calls: Some(tool_calls),
+            tool_call_id: None,
+        }
+    }
+}
+
+/// A tool the model may call, described the way OpenAI-compatible APIs
+/// expect: a JSON Schema object under `parameters`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub function: ToolFunctionDef,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolFunctionDef {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ToolDefinition {
+    pub fn new(name: impl Into<String>, description: impl Into<String>, parameters: serde_json::Value) -> Self {
+        Self {
+            kind: "function",
+            function: ToolFunctionDef {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolCallRequest {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: ToolFunctionCall,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolFunctionCall {
+    pub name: String,
+    pub arguments: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -31,8 +124,60 @@ struct OpenRouterChoice {
 
 #[derive(Debug, Deserialize)]
 struct OpenRouterResponseMessage {
+    #[allow(dead_code)]
     role: String,
-    content: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCallRequest>>,
+}
+
+type ToolFuture = Pin<Box<dyn Future<Output = Result<String>> + Send>>;
+type ToolHandler = Arc<dyn Fn(serde_json::Value) -> ToolFuture + Send + Sync>;
+
+/// Maps tool names to their JSON Schema definition and the async handler
+/// that executes a call, so `send_message_with_tools` can both advertise
+/// the tools to the model and dispatch the calls it asks for.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    definitions: Vec<ToolDefinition>,
+    handlers: HashMap<String, ToolHandler>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` to run whenever the model calls the tool named
+    /// in `definition`.
+    pub fn register<F, Fut>(&mut self, definition: ToolDefinition, handler: F)
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        let name = definition.function.name.clone();
+        self.handlers.insert(name, Arc::new(move |args| Box::pin(handler(args))));
+        self.definitions.push(definition);
+    }
+
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        self.definitions.clone()
+    }
+
+    async fn dispatch(&self, name: &str, arguments: &str) -> Result<String> {
+        let handler = self
+            .handlers
+            .get(name)
+            .ok_or_else(|| anyhow!("No handler registered for tool `{}`", name))?;
+        let args: serde_json::Value = if arguments.trim().is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_str(arguments)
+                .map_err(|e| anyhow!("Invalid arguments for tool `{}`: {}", name, e))?
+        };
+        handler(args).await
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,73 +187,302 @@ struct OpenRouterUsage {
     total_tokens: u32,
 }
 
+#[derive(Debug, Serialize)]
+struct OpenRouterEmbeddingsRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterEmbeddingsResponse {
+    data: Vec<OpenRouterEmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterEmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterStreamChunk {
+    choices: Vec<OpenRouterStreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterStreamChoice {
+    delta: OpenRouterDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenRouterDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
 pub struct OpenRouterClient {
     api_key: String,
     model: String,
+    max_tokens: u32,
     client: reqwest::Client,
 }
 
 impl OpenRouterClient {
-    pub fn new(api_key: &str, model: &str) -> Self {
+    pub fn new(api_key: &str, model: &str, max_tokens: u32) -> Self {
         Self {
             api_key: api_key.to_string(),
             model: model.to_string(),
+            max_tokens,
             client: reqwest::Client::builder()
                 .timeout(Duration::from_secs(120))
                 .build()
                 .expect("Failed to create HTTP client"),
         }
     }
-    
+
     pub async fn send_message(&self, system_prompt: &str, user_message: &str) -> Result<String> {
         let request = OpenRouterRequest {
             model: self.model.clone(),
             messages: vec![
-                OpenRouterMessage {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                OpenRouterMessage {
-                    role: "user".to_string(),
-                    content: user_message.to_string(),
-                },
+                OpenRouterMessage::system(system_prompt),
+                OpenRouterMessage::user(user_message),
             ],
             temperature: 0.7,
-            max_tokens: Some(4000),
+            max_tokens: Some(self.max_tokens),
             stream: false,
+            tools: None,
         };
-        
+
+        let choice = self.complete(&request).await?;
+        Ok(choice.message.content.unwrap_or_default())
+    }
+
+    async fn complete(&self, request: &OpenRouterRequest) -> Result<OpenRouterChoice> {
         let response = self.client
             .post("https://openrouter.ai/api/v1/chat/completions")
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("HTTP-Referer", "https://github.com/ai-cli-agent")
             .header("X-Title", "AI CLI Agent")
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            return Err(anyhow!("API request failed: {} - {}", status, error_text));
+        }
+
+        let mut openrouter_response: OpenRouterResponse = response.json().await?;
+
+        if openrouter_response.choices.is_empty() {
+            return Err(anyhow!("No response choices received"));
+        }
+
+        Ok(openrouter_response.choices.remove(0))
+    }
+
+    /// Requests embedding vectors for `texts` from OpenRouter's
+    /// `/embeddings` endpoint, returned in the same order as `texts`
+    /// (the API's `index` field is used to re-sort, since providers
+    /// aren't guaranteed to respond in request order).
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = OpenRouterEmbeddingsRequest {
+            model: self.model.clone(),
+            input: texts.to_vec(),
+        };
+
+        let response = self.client
+            .post("https://openrouter.ai/api/v1/embeddings")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("HTTP-Referer", "https://github.com/ai-cli-agent")
+            .header("X-Title", "AI CLI Agent")
             .json(&request)
             .send()
             .await?;
-        
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            return Err(anyhow!("Embeddings request failed: {} - {}", status, error_text));
+        }
+
+        let mut data = response.json::<OpenRouterEmbeddingsResponse>().await?.data;
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    /// Like `send_message`, but sets `stream: true` and reads the response
+    /// as Server-Sent Events instead of waiting for the full completion.
+    /// Each `data: ` frame's `choices[0].delta.content` is handed to
+    /// `on_token` as it arrives (so the CLI can print tokens live instead
+    /// of blocking for up to the client's 120s timeout), and also
+    /// accumulated into the string this returns once the stream ends.
+    /// Frames split across read boundaries are buffered until a full
+    /// `\n\n`-delimited event is available; the terminal `[DONE]` sentinel
+    /// is ignored.
+    pub async fn send_message_stream(
+        &self,
+        system_prompt: &str,
+        user_message: &str,
+        mut on_token: impl FnMut(&str),
+    ) -> Result<String> {
+        let request = OpenRouterRequest {
+            model: self.model.clone(),
+            messages: vec![
+                OpenRouterMessage::system(system_prompt),
+                OpenRouterMessage::user(user_message),
+            ],
+            temperature: 0.7,
+            max_tokens: Some(self.max_tokens),
+            stream: true,
+            tools: None,
+        };
+
+        let response = self.client
+            .post("https://openrouter.ai/api/v1/chat/completions")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("HTTP-Referer", "https://github.com/ai-cli-agent")
+            .header("X-Title", "AI CLI Agent")
+            .json(&request)
+            .send()
+            .await?;
+
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await?;
             return Err(anyhow!("API request failed: {} - {}", status, error_text));
         }
-        
-        let openrouter_response: OpenRouterResponse = response.json().await?;
-        
-        if let Some(choice) = openrouter_response.choices.first() {
-            Ok(choice.message.content.clone())
-        } else {
-            Err(anyhow!("No response choices received"))
+
+        let mut stream = response.bytes_stream();
+        let mut buffer = String::new();
+        let mut full_text = String::new();
+
+        while let Some(chunk) = stream.next().await {
+            buffer.push_str(&String::from_utf8_lossy(&chunk?));
+
+            while let Some(event_end) = buffer.find("\n\n") {
+                let event = buffer[..event_end].to_string();
+                buffer.drain(..event_end + 2);
+
+                for line in event.lines() {
+                    let Some(data) = line.strip_prefix("data: ") else {
+                        continue;
+                    };
+                    if data == "[DONE]" {
+                        continue;
+                    }
+
+                    let Ok(chunk) = serde_json::from_str::<OpenRouterStreamChunk>(data) else {
+                        continue;
+                    };
+                    let Some(content) = chunk.choices.into_iter().next().and_then(|c| c.delta.content) else {
+                        continue;
+                    };
+
+                    on_token(&content);
+                    full_text.push_str(&content);
+                }
+            }
         }
+
+        Ok(full_text)
     }
-    
+
+    /// Runs a tool-calling conversation: sends `system_prompt`/`user_message`
+    /// plus `registry`'s tool definitions, and whenever the model's
+    /// `finish_reason` is `tool_calls`, dispatches each requested call
+    /// through `registry`, appends the results as `role: "tool"` messages,
+    /// and resends. Stops once the model replies with `stop` or after
+    /// `max_steps` round trips, whichever comes first, to bound runaway
+    /// tool-call loops. A replayed call (same `tool_call_id`) reuses its
+    /// cached result instead of re-executing.
+    pub async fn send_message_with_tools(
+        &self,
+        system_prompt: &str,
+        user_message: &str,
+        registry: &ToolRegistry,
+        max_steps: usize,
+    ) -> Result<String> {
+        let mut messages = vec![
+            OpenRouterMessage::system(system_prompt),
+            OpenRouterMessage::user(user_message),
+        ];
+        let mut result_cache: HashMap<String, String> = HashMap::new();
+
+        for _ in 0..max_steps {
+            let request = OpenRouterRequest {
+                model: self.model.clone(),
+                messages: messages.clone(),
+                temperature: 0.7,
+                max_tokens: Some(self.max_tokens),
+                stream: false,
+                tools: Some(registry.definitions()),
+            };
+
+            let choice = self.complete(&request).await?;
+
+            let tool_calls = match &choice.message.tool_calls {
+                Some(calls) if choice.finish_reason == "tool_calls" && !calls.is_empty() => calls.clone(),
+                _ => return Ok(choice.message.content.unwrap_or_default()),
+            };
+
+            messages.push(OpenRouterMessage::assistant_tool_calls(tool_calls.clone()));
+
+            for call in &tool_calls {
+                let output = match result_cache.get(&call.id) {
+                    Some(cached) => cached.clone(),
+                    None => {
+                        let output = registry
+                            .dispatch(&call.function.name, &call.function.arguments)
+                            .await
+                            .unwrap_or_else(|e| format!("Error: {}", e));
+                        result_cache.insert(call.id.clone(), output.clone());
+                        output
+                    }
+                };
+                messages.push(OpenRouterMessage::tool_result(&call.id, &output));
+            }
+        }
+
+        Err(anyhow!("Exceeded max_steps ({}) without a final response", max_steps))
+    }
+
+
+    /// Like `send_message`, but packs `context_chunks` (e.g. file
+    /// snippets) into the request via `tokenizer::fit_context` instead of
+    /// concatenating them blindly, so a request too large for the
+    /// model's window gets trimmed rather than rejected by the API.
     pub async fn send_message_with_context(
         &self,
         system_prompt: &str,
         user_message: &str,
-        context: &str,
+        context_chunks: &[crate::tokenizer::ContextChunk],
     ) -> Result<String> {
-        let full_message = format!("{}\n\nContext:\n{}", user_message, context);
+        let bpe = crate::tokenizer::resolve_encoding(&self.model)?;
+        let budget = crate::tokenizer::context_window_for_model(&self.model);
+        let fit = crate::tokenizer::fit_context(
+            &bpe,
+            system_prompt,
+            user_message,
+            context_chunks,
+            budget,
+            self.max_tokens as usize,
+        );
+
+        println!(
+            "ℹ️  Context tokens: system={} user={} context={} (budget {})",
+            fit.system_tokens, fit.user_tokens, fit.context_tokens, budget
+        );
+        if !fit.dropped.is_empty() {
+            println!("⚠️  Dropped from context to stay in budget: {}", fit.dropped.join(", "));
+        }
+
+        let full_message = if fit.chunks.is_empty() {
+            user_message.to_string()
+        } else {
+            format!("{}\n\nContext:\n{}", user_message, fit.chunks.join("\n\n"))
+        };
         self.send_message(system_prompt, &full_message).await
     }
     
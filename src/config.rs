@@ -1,6 +1,8 @@
+use crate::filesystem::FileSystem;
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use dirs;
 
@@ -15,6 +17,37 @@ pub struct Config {
     pub auto_save: bool,
     pub backup_enabled: bool,
     pub backup_dir: PathBuf,
+    #[serde(default = "default_max_backups_per_file")]
+    pub max_backups_per_file: usize,
+    #[serde(default = "default_max_backup_age_days")]
+    pub max_backup_age_days: u64,
+    #[serde(default = "default_index_dir")]
+    pub index_dir: PathBuf,
+    #[serde(default)]
+    pub remote: Option<RemoteConfig>,
+}
+
+fn default_index_dir() -> PathBuf {
+    PathBuf::from(".ai_cli_index")
+}
+
+fn default_max_backups_per_file() -> usize {
+    10
+}
+
+fn default_max_backup_age_days() -> u64 {
+    30
+}
+
+/// Where and how to reach a remote host so the agent can edit files
+/// there over SFTP (`filesystem::SftpFs`) instead of on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub key_path: PathBuf,
+    pub root_dir: PathBuf,
 }
 
 impl Default for Config {
@@ -29,6 +62,10 @@ impl Default for Config {
             auto_save: true,
             backup_enabled: true,
             backup_dir: PathBuf::from(".ai_cli_backups"),
+            max_backups_per_file: default_max_backups_per_file(),
+            max_backup_age_days: default_max_backup_age_days(),
+            index_dir: default_index_dir(),
+            remote: None,
         }
     }
 }
@@ -143,26 +180,139 @@ impl Config {
         ]
     }
     
-    pub async fn backup_file(&self, file_path: &PathBuf) -> Result<PathBuf> {
+    pub async fn backup_file(&self, fs: &dyn FileSystem, file_path: &PathBuf) -> Result<PathBuf> {
         if !self.backup_enabled {
             return Ok(file_path.clone());
         }
-        
-        if !self.backup_dir.exists() {
-            fs::create_dir_all(&self.backup_dir).await?;
+
+        if !fs.exists(&self.backup_dir).await {
+            fs.create_dir_all(&self.backup_dir).await?;
         }
-        
+
         let file_name = file_path.file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown");
-        
+
         let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
         let backup_name = format!("{}_{}_backup", file_name, timestamp);
         let backup_path = self.backup_dir.join(backup_name);
-        
-        fs::copy(file_path, &backup_path).await?;
-        
+
+        fs.copy(file_path, &backup_path).await?;
+
         println!("ðŸ“¦ Backup created: {}", backup_path.display());
         Ok(backup_path)
     }
+
+    /// Lists backups of `original_path` found in `backup_dir`, newest
+    /// first, by parsing the `{name}_{timestamp}_backup` convention
+    /// `backup_file` writes.
+    pub async fn list_backups(&self, fs: &dyn FileSystem, original_path: &Path) -> Result<Vec<BackupEntry>> {
+        let file_name = original_path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+
+        let mut entries = Vec::new();
+        if !fs.exists(&self.backup_dir).await {
+            return Ok(entries);
+        }
+
+        for entry in fs.read_dir(&self.backup_dir).await? {
+            if entry.is_dir {
+                continue;
+            }
+            if let Some((name, timestamp)) = parse_backup_name(&entry.name) {
+                if name == file_name {
+                    entries.push(BackupEntry {
+                        path: self.backup_dir.join(&entry.name),
+                        original_name: name,
+                        timestamp,
+                    });
+                }
+            }
+        }
+
+        entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(entries)
+    }
+
+    /// Copies `backup_path` back over `target`. Takes a fresh backup of
+    /// whatever is currently at `target` first, so the restore itself is
+    /// undoable through the same history.
+    pub async fn restore_backup(&self, fs: &dyn FileSystem, backup_path: &Path, target: &Path) -> Result<PathBuf> {
+        if fs.exists(target).await {
+            self.backup_file(fs, &target.to_path_buf()).await?;
+        }
+
+        fs.copy(backup_path, target).await?;
+        println!("â™»ï¸  Restored {} from {}", target.display(), backup_path.display());
+        Ok(target.to_path_buf())
+    }
+
+    /// Deletes backups beyond the retention policy: anything older than
+    /// `max_age_days`, plus the oldest entries past `max_per_file` most
+    /// recent versions for each original file. Returns how many were
+    /// deleted.
+    pub async fn prune_backups(&self, fs: &dyn FileSystem, max_per_file: usize, max_age_days: u64) -> Result<usize> {
+        if !fs.exists(&self.backup_dir).await {
+            return Ok(0);
+        }
+
+        let mut by_file: HashMap<String, Vec<BackupEntry>> = HashMap::new();
+        for entry in fs.read_dir(&self.backup_dir).await? {
+            if entry.is_dir {
+                continue;
+            }
+            if let Some((name, timestamp)) = parse_backup_name(&entry.name) {
+                by_file.entry(name.clone()).or_default().push(BackupEntry {
+                    path: self.backup_dir.join(&entry.name),
+                    original_name: name,
+                    timestamp,
+                });
+            }
+        }
+
+        let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(max_age_days as i64);
+        let mut deleted = 0;
+
+        for mut versions in by_file.into_values() {
+            versions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+            for (rank, entry) in versions.into_iter().enumerate() {
+                if rank >= max_per_file || entry.timestamp < cutoff {
+                    fs.remove_file(&entry.path).await?;
+                    deleted += 1;
+                }
+            }
+        }
+
+        if deleted > 0 {
+            println!("ðŸ§¹ Pruned {} backup(s)", deleted);
+        }
+        Ok(deleted)
+    }
+}
+
+/// One backup found in `backup_dir`: which original file it belongs to,
+/// when it was taken, and where it lives.
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub original_name: String,
+    pub timestamp: chrono::NaiveDateTime,
+}
+
+/// Parses a `{name}_{timestamp}_backup` backup filename (as written by
+/// `Config::backup_file`) back into the original file name and the
+/// `NaiveDateTime` it was taken at.
+fn parse_backup_name(name: &str) -> Option<(String, chrono::NaiveDateTime)> {
+    let stem = name.strip_suffix("_backup")?;
+    let (rest, time_part) = stem.rsplit_once('_')?;
+    let (original_name, date_part) = rest.rsplit_once('_')?;
+    let timestamp = chrono::NaiveDateTime::parse_from_str(
+        &format!("{}_{}", date_part, time_part),
+        "%Y%m%d_%H%M%S",
+    )
+    .ok()?;
+
+    Some((original_name.to_string(), timestamp))
 }
\ No newline at end of file
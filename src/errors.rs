@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The agent's typed failure taxonomy. Unlike a bare `anyhow!(...)` string,
+/// each variant lets callers (notably `execute_task`'s retry loop) decide
+/// per-kind whether retrying the action makes sense at all.
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("file not found: {0}")]
+    FileNotFound(PathBuf),
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(PathBuf),
+
+    #[error("command failed with exit code {code}\nstdout: {stdout}\nstderr: {stderr}")]
+    CommandFailed {
+        code: i32,
+        stdout: String,
+        stderr: String,
+    },
+
+    #[error("command timed out after {0:?}")]
+    CommandTimedOut(Duration),
+
+    #[error("failed to parse tool call: {0}")]
+    ParseError(String),
+
+    #[error("file changed since it was read: {0}")]
+    Conflict(PathBuf),
+}
+
+impl AgentError {
+    /// Whether it's worth letting the model try the same kind of action
+    /// again. A missing file or a failed command may succeed next time
+    /// (the model can write the file first, or fix its arguments); a
+    /// permission error won't resolve itself through retrying.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, AgentError::PermissionDenied(_))
+    }
+}
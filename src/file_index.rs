@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::utils::hash_content;
+
+/// Bytes read from the front of a file when guessing whether it's binary.
+const BINARY_SNIFF_SIZE: usize = 8192;
+
+#[derive(Debug, Clone)]
+struct FileRecord {
+    hash: String,
+    size: u64,
+    modified: u64,
+}
+
+/// A content-addressed index over a work directory, so the agent can find
+/// duplicate files and grep file bodies instead of only matching names.
+/// Re-walks the tree on every `refresh`, but only re-hashes files whose
+/// size or modified time changed since the last pass.
+pub struct FileIndex {
+    work_dir: PathBuf,
+    records: HashMap<PathBuf, FileRecord>,
+}
+
+impl FileIndex {
+    pub fn new(work_dir: &Path) -> Self {
+        Self {
+            work_dir: work_dir.to_path_buf(),
+            records: HashMap::new(),
+        }
+    }
+
+    /// Walks `work_dir`, hashing (BLAKE3) any file whose size/modified-time
+    /// signature differs from what's recorded, and drops entries for files
+    /// that no longer exist.
+    pub async fn refresh(&mut self) -> Result<()> {
+        let mut seen = Vec::new();
+
+        for entry in WalkDir::new(&self.work_dir).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let size = meta.len();
+
+            seen.push(path.to_path_buf());
+
+            if let Some(existing) = self.records.get(path) {
+                if existing.size == size && existing.modified == modified {
+                    continue;
+                }
+            }
+
+            let Ok(content) = tokio::fs::read(path).await else {
+                continue;
+            };
+            let hash = hash_content(&String::from_utf8_lossy(&content));
+
+            self.records.insert(path.to_path_buf(), FileRecord { hash, size, modified });
+        }
+
+        self.records.retain(|path, _| seen.contains(path));
+        Ok(())
+    }
+
+    /// Groups indexed files by identical content hash, keeping only groups
+    /// with more than one member.
+    pub fn find_duplicates(&self) -> Vec<Vec<PathBuf>> {
+        let mut by_hash: HashMap<&str, Vec<PathBuf>> = HashMap::new();
+        for (path, record) in &self.records {
+            by_hash.entry(record.hash.as_str()).or_default().push(path.clone());
+        }
+
+        let mut groups: Vec<Vec<PathBuf>> = by_hash
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    /// Greps indexed files for `needle`, skipping anything that looks
+    /// binary (a null byte in the first `BINARY_SNIFF_SIZE` bytes).
+    pub async fn search_by_content(&self, needle: &str) -> Result<Vec<PathBuf>> {
+        let mut matches = Vec::new();
+
+        for path in self.records.keys() {
+            let Ok(bytes) = tokio::fs::read(path).await else {
+                continue;
+            };
+            if is_binary(&bytes) {
+                continue;
+            }
+
+            let content = String::from_utf8_lossy(&bytes);
+            if content.contains(needle) {
+                matches.push(path.clone());
+            }
+        }
+
+        matches.sort();
+        Ok(matches)
+    }
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_SNIFF_SIZE).any(|&b| b == 0)
+}
@@ -1,216 +1,1010 @@
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures_util::Stream;
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
-use std::env;
-use walkdir::WalkDir;
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::ai::OpenRouterClient;
+use crate::errors::AgentError;
+use crate::filesystem::FileSystem;
+
+/// Maps an I/O error from a path-based operation onto the typed taxonomy
+/// so callers further up (the retry loop in `execute_task`) can tell a
+/// permission problem apart from anything else.
+fn classify_io_error(err: std::io::Error, path: &Path) -> anyhow::Error {
+    match err.kind() {
+        ErrorKind::NotFound => AgentError::FileNotFound(path.to_path_buf()).into(),
+        ErrorKind::PermissionDenied => AgentError::PermissionDenied(path.to_path_buf()).into(),
+        _ => anyhow!("{}: {}", path.display(), err),
+    }
+}
+
+/// Walks `root` honoring `options`, capping depth at whichever of
+/// `options.max_depth` and `depth_cap` is tighter (`depth_cap` lets
+/// `list_directory` reuse this for a single, non-recursive level while
+/// `search_files` passes `None` for an unbounded walk).
+fn walk_matching(root: &Path, options: &SearchOptions, depth_cap: Option<usize>) -> Result<Vec<String>> {
+    let mut override_builder = OverrideBuilder::new(root);
+    for pattern in &options.patterns {
+        override_builder
+            .add(pattern)
+            .map_err(|e| anyhow!("invalid glob pattern '{}': {}", pattern, e))?;
+    }
+    let overrides = override_builder.build()?;
+
+    let mut builder = WalkBuilder::new(root);
+    builder.standard_filters(options.respect_gitignore).overrides(overrides);
+
+    let depth = match (options.max_depth, depth_cap) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (Some(a), None) => Some(a),
+        (None, cap) => cap,
+    };
+    if let Some(depth) = depth {
+        builder.max_depth(Some(depth));
+    }
+
+    let mut entries = Vec::new();
+    for entry in builder.build().filter_map(|e| e.ok()) {
+        if entry.path() == root {
+            continue;
+        }
+        let kind = if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            "DIR"
+        } else {
+            "FILE"
+        };
+        entries.push(format!("{} [{}]", entry.path().display(), kind));
+    }
+
+    entries.sort();
+    Ok(entries)
+}
+
+/// Controls how `Fs::write_file` lands a write. Atomic writes go to a
+/// temp file beside `path` and get renamed into place, so a reader never
+/// observes a truncated file and a crash mid-write leaves the original
+/// untouched; `preserve_line_endings` re-encodes the new content to match
+/// an existing file's CRLF/LF convention and trailing-newline state so an
+/// edit to a CRLF file doesn't turn into a whole-file diff.
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    pub atomic: bool,
+    pub preserve_line_endings: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            atomic: true,
+            preserve_line_endings: true,
+        }
+    }
+}
+
+/// Re-encodes `new_content` to match `existing`'s line-ending style
+/// (CRLF if `existing` contains any, LF otherwise) and trailing-newline
+/// state, ignoring whatever `new_content` itself did.
+fn reencode_to_match(existing: &str, new_content: &str) -> String {
+    let ending = if existing.contains("\r\n") { "\r\n" } else { "\n" };
+    let had_trailing_newline = existing.ends_with('\n');
+
+    let mut normalized = new_content.replace("\r\n", "\n");
+    if normalized.ends_with('\n') {
+        normalized.pop();
+    }
+
+    let mut body = normalized.replace('\n', ending);
+    if had_trailing_newline {
+        body.push_str(ending);
+    }
+    body
+}
+
+fn temp_sibling_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("tmp");
+    path.with_file_name(format!(".{}.tmp-{}", file_name, uuid::Uuid::new_v4()))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CreateOptions {
+    pub overwrite: bool,
+    pub ignore_if_exists: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions {
+    pub overwrite: bool,
+    pub ignore_if_exists: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenameOptions {
+    pub overwrite: bool,
+    pub ignore_if_exists: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOptions {
+    pub recursive: bool,
+    pub ignore_if_not_exists: bool,
+}
+
+/// Controls how `search_files` and `list_directory` walk the tree:
+/// `patterns` are gitignore-style globs evaluated in order with the last
+/// match winning (`!target/**` after `**/*.rs` keeps Rust files everywhere
+/// except under `target/`), and `respect_gitignore` toggles honoring `.gitignore`/
+/// `.ignore` files found along the way, same as ripgrep's defaults.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub patterns: Vec<String>,
+    pub respect_gitignore: bool,
+    pub max_depth: Option<usize>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            respect_gitignore: true,
+            max_depth: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub size: u64,
+    pub modified: Option<u64>,
+}
+
+/// The operations `FileManager` needs from a backing store. `RealFs`
+/// backs it with `tokio::fs` (today's behavior); `FakeFs` backs it with
+/// an in-memory map so tool calls can be exercised deterministically in
+/// tests, and other backends (remote, sandboxed) can be swapped in the
+/// same way later.
+#[async_trait]
+pub trait Fs: Send + Sync {
+    async fn read_file(&self, path: &Path) -> Result<String>;
+    async fn write_file(&self, path: &Path, content: &str, options: WriteOptions) -> Result<()>;
+    async fn delete_path(&self, path: &Path, options: RemoveOptions) -> Result<()>;
+    async fn list_directory(&self, path: &Path) -> Result<Vec<String>>;
+    async fn copy_file(&self, src: &Path, dst: &Path, options: CopyOptions) -> Result<()>;
+    async fn move_file(&self, src: &Path, dst: &Path, options: RenameOptions) -> Result<()>;
+    async fn create_directory(&self, path: &Path, options: CreateOptions) -> Result<()>;
+    async fn metadata(&self, path: &Path) -> Result<Metadata>;
+}
+
+/// `Fs` over the real local filesystem via `tokio::fs`.
+pub struct RealFs;
+
+#[async_trait]
+impl Fs for RealFs {
+    async fn read_file(&self, path: &Path) -> Result<String> {
+        fs::read_to_string(path).await.map_err(|e| classify_io_error(e, path))
+    }
+
+    async fn write_file(&self, path: &Path, content: &str, options: WriteOptions) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let content = if options.preserve_line_endings {
+            match fs::read_to_string(path).await {
+                Ok(existing) => reencode_to_match(&existing, content),
+                Err(_) => content.to_string(),
+            }
+        } else {
+            content.to_string()
+        };
+
+        if options.atomic {
+            let tmp_path = temp_sibling_path(path);
+            fs::write(&tmp_path, &content).await.map_err(|e| classify_io_error(e, path))?;
+            fs::rename(&tmp_path, path).await.map_err(|e| classify_io_error(e, path))
+        } else {
+            fs::write(path, &content).await.map_err(|e| classify_io_error(e, path))
+        }
+    }
+
+    async fn delete_path(&self, path: &Path, options: RemoveOptions) -> Result<()> {
+        if !path.exists() {
+            if options.ignore_if_not_exists {
+                return Ok(());
+            }
+            return Err(AgentError::FileNotFound(path.to_path_buf()).into());
+        }
+
+        let result = if path.is_dir() {
+            if options.recursive {
+                fs::remove_dir_all(path).await
+            } else {
+                fs::remove_dir(path).await
+            }
+        } else {
+            fs::remove_file(path).await
+        };
+
+        result.map_err(|e| classify_io_error(e, path))
+    }
+
+    async fn list_directory(&self, path: &Path) -> Result<Vec<String>> {
+        if !path.exists() {
+            return Err(anyhow!("Directory not found: {}", path.display()));
+        }
+        if !path.is_dir() {
+            return Err(anyhow!("Path is not a directory: {}", path.display()));
+        }
+
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(path).await?;
+
+        while let Some(entry) = dir.next_entry().await? {
+            let entry_path = entry.path();
+            let name = entry_path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string();
+
+            let metadata = entry.metadata().await?;
+            let file_type = if metadata.is_dir() {
+                "DIR"
+            } else if metadata.is_file() {
+                "FILE"
+            } else {
+                "OTHER"
+            };
+
+            entries.push(format!("{} [{}]", name, file_type));
+        }
+
+        entries.sort();
+        Ok(entries)
+    }
+
+    async fn copy_file(&self, src: &Path, dst: &Path, options: CopyOptions) -> Result<()> {
+        if dst.exists() {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::copy(src, dst).await.map_err(|e| classify_io_error(e, src))?;
+        Ok(())
+    }
+
+    async fn move_file(&self, src: &Path, dst: &Path, options: RenameOptions) -> Result<()> {
+        if dst.exists() {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::rename(src, dst).await.map_err(|e| classify_io_error(e, src))?;
+        Ok(())
+    }
+
+    async fn create_directory(&self, path: &Path, options: CreateOptions) -> Result<()> {
+        if path.exists() {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Directory already exists: {}", path.display()));
+            }
+        }
+
+        fs::create_dir_all(path).await.map_err(|e| classify_io_error(e, path))
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let metadata = fs::metadata(path).await.map_err(|e| classify_io_error(e, path))?;
+        let modified = metadata.modified().ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        Ok(Metadata {
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// `Fs` over any `crate::filesystem::FileSystem` backend, so `FileManager`
+/// (and therefore every tool call that goes through it) can be pointed at
+/// `filesystem::SftpFs` the same way it's pointed at `RealFs`, instead of
+/// `FileSystem` only ever reaching the backup subsystem. Delegates
+/// atomic writes and temp-file naming to the wrapped `FileSystem` rather
+/// than duplicating `temp_sibling_path`/rename-into-place here.
+pub struct FsAdapter<T: FileSystem>(pub T);
+
+#[async_trait]
+impl<T: FileSystem> Fs for FsAdapter<T> {
+    async fn read_file(&self, path: &Path) -> Result<String> {
+        self.0.read_to_string(path).await
+    }
+
+    async fn write_file(&self, path: &Path, content: &str, options: WriteOptions) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            self.0.create_dir_all(parent).await?;
+        }
+
+        let content = if options.preserve_line_endings {
+            match self.0.read_to_string(path).await {
+                Ok(existing) => reencode_to_match(&existing, content),
+                Err(_) => content.to_string(),
+            }
+        } else {
+            content.to_string()
+        };
+
+        if options.atomic {
+            self.0.write_atomic(path, content.as_bytes()).await
+        } else {
+            self.0.write(path, content.as_bytes()).await
+        }
+    }
+
+    async fn delete_path(&self, path: &Path, options: RemoveOptions) -> Result<()> {
+        if !self.0.exists(path).await {
+            if options.ignore_if_not_exists {
+                return Ok(());
+            }
+            return Err(AgentError::FileNotFound(path.to_path_buf()).into());
+        }
+        self.0.remove_file(path).await
+    }
+
+    async fn list_directory(&self, path: &Path) -> Result<Vec<String>> {
+        if !self.0.exists(path).await {
+            return Err(anyhow!("Directory not found: {}", path.display()));
+        }
+
+        let mut entries: Vec<String> = self
+            .0
+            .read_dir(path)
+            .await?
+            .into_iter()
+            .map(|entry| {
+                let kind = if entry.is_dir { "DIR" } else { "FILE" };
+                format!("{} [{}]", entry.name, kind)
+            })
+            .collect();
+        entries.sort();
+        Ok(entries)
+    }
+
+    async fn copy_file(&self, src: &Path, dst: &Path, options: CopyOptions) -> Result<()> {
+        if self.0.exists(dst).await {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+        if let Some(parent) = dst.parent() {
+            self.0.create_dir_all(parent).await?;
+        }
+        self.0.copy(src, dst).await
+    }
+
+    async fn move_file(&self, src: &Path, dst: &Path, options: RenameOptions) -> Result<()> {
+        if self.0.exists(dst).await {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+        if let Some(parent) = dst.parent() {
+            self.0.create_dir_all(parent).await?;
+        }
+        self.0.rename(src, dst).await
+    }
+
+    async fn create_directory(&self, path: &Path, options: CreateOptions) -> Result<()> {
+        if self.0.exists(path).await {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Directory already exists: {}", path.display()));
+            }
+        }
+        self.0.create_dir_all(path).await
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let metadata = self.0.metadata(path).await?;
+        Ok(Metadata {
+            is_dir: metadata.is_dir,
+            is_file: metadata.is_file,
+            size: metadata.len,
+            modified: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FakeEntry {
+    File(String),
+    Dir,
+}
+
+/// `Fs` backed by an in-memory `BTreeMap`, for exercising `FileManager`
+/// and tool-call dispatch without touching the real disk.
+pub struct FakeFs {
+    entries: Mutex<BTreeMap<PathBuf, FakeEntry>>,
+}
+
+impl FakeFs {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Default for FakeFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn ensure_fake_dir(entries: &mut BTreeMap<PathBuf, FakeEntry>, path: &Path) {
+    let mut current = PathBuf::new();
+    for component in path.components() {
+        current.push(component);
+        entries.entry(current.clone()).or_insert(FakeEntry::Dir);
+    }
+}
+
+#[async_trait]
+impl Fs for FakeFs {
+    async fn read_file(&self, path: &Path) -> Result<String> {
+        let entries = self.entries.lock().await;
+        match entries.get(path) {
+            Some(FakeEntry::File(content)) => Ok(content.clone()),
+            Some(FakeEntry::Dir) => Err(anyhow!("{} is a directory", path.display())),
+            None => Err(AgentError::FileNotFound(path.to_path_buf()).into()),
+        }
+    }
+
+    async fn write_file(&self, path: &Path, content: &str, options: WriteOptions) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        if let Some(parent) = path.parent() {
+            ensure_fake_dir(&mut entries, parent);
+        }
+
+        let content = if options.preserve_line_endings {
+            match entries.get(path) {
+                Some(FakeEntry::File(existing)) => reencode_to_match(existing, content),
+                _ => content.to_string(),
+            }
+        } else {
+            content.to_string()
+        };
+
+        entries.insert(path.to_path_buf(), FakeEntry::File(content));
+        Ok(())
+    }
+
+    async fn delete_path(&self, path: &Path, options: RemoveOptions) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        if !entries.contains_key(path) {
+            if options.ignore_if_not_exists {
+                return Ok(());
+            }
+            return Err(AgentError::FileNotFound(path.to_path_buf()).into());
+        }
+
+        if matches!(entries.get(path), Some(FakeEntry::Dir)) {
+            if !options.recursive && entries.keys().any(|p| p != path && p.starts_with(path)) {
+                return Err(anyhow!("Directory not empty: {}", path.display()));
+            }
+            let prefix = path.to_path_buf();
+            entries.retain(|p, _| p != &prefix && !p.starts_with(&prefix));
+        } else {
+            entries.remove(path);
+        }
+
+        Ok(())
+    }
+
+    async fn list_directory(&self, path: &Path) -> Result<Vec<String>> {
+        let entries = self.entries.lock().await;
+        if !entries.contains_key(path) {
+            return Err(anyhow!("Directory not found: {}", path.display()));
+        }
+
+        let mut names: Vec<String> = entries
+            .iter()
+            .filter_map(|(p, entry)| {
+                if p.parent() != Some(path) {
+                    return None;
+                }
+                let name = p.file_name()?.to_str()?.to_string();
+                let kind = match entry {
+                    FakeEntry::Dir => "DIR",
+                    FakeEntry::File(_) => "FILE",
+                };
+                Some(format!("{} [{}]", name, kind))
+            })
+            .collect();
+
+        names.sort();
+        Ok(names)
+    }
+
+    async fn copy_file(&self, src: &Path, dst: &Path, options: CopyOptions) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        let content = match entries.get(src) {
+            Some(FakeEntry::File(content)) => content.clone(),
+            _ => return Err(AgentError::FileNotFound(src.to_path_buf()).into()),
+        };
+
+        if entries.contains_key(dst) {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+
+        if let Some(parent) = dst.parent() {
+            ensure_fake_dir(&mut entries, parent);
+        }
+        entries.insert(dst.to_path_buf(), FakeEntry::File(content));
+        Ok(())
+    }
+
+    async fn move_file(&self, src: &Path, dst: &Path, options: RenameOptions) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        let content = match entries.get(src) {
+            Some(FakeEntry::File(content)) => content.clone(),
+            _ => return Err(AgentError::FileNotFound(src.to_path_buf()).into()),
+        };
+
+        if entries.contains_key(dst) {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Destination already exists: {}", dst.display()));
+            }
+        }
+
+        if let Some(parent) = dst.parent() {
+            ensure_fake_dir(&mut entries, parent);
+        }
+        entries.remove(src);
+        entries.insert(dst.to_path_buf(), FakeEntry::File(content));
+        Ok(())
+    }
+
+    async fn create_directory(&self, path: &Path, options: CreateOptions) -> Result<()> {
+        let mut entries = self.entries.lock().await;
+        if entries.contains_key(path) {
+            if options.ignore_if_exists {
+                return Ok(());
+            }
+            if !options.overwrite {
+                return Err(anyhow!("Directory already exists: {}", path.display()));
+            }
+        }
+
+        ensure_fake_dir(&mut entries, path);
+        Ok(())
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let entries = self.entries.lock().await;
+        match entries.get(path) {
+            Some(FakeEntry::File(content)) => Ok(Metadata {
+                is_dir: false,
+                is_file: true,
+                size: content.len() as u64,
+                modified: None,
+            }),
+            Some(FakeEntry::Dir) => Ok(Metadata {
+                is_dir: true,
+                is_file: false,
+                size: 0,
+                modified: None,
+            }),
+            None => Err(AgentError::FileNotFound(path.to_path_buf()).into()),
+        }
+    }
+}
 
 pub struct FileManager {
     work_dir: PathBuf,
+    fs: Arc<dyn Fs>,
 }
 
 impl FileManager {
-    pub fn new(work_dir: &Path) -> Self {
+    pub fn new(work_dir: &Path, fs: Arc<dyn Fs>) -> Self {
         Self {
             work_dir: work_dir.to_path_buf(),
+            fs,
         }
     }
-    
+
     pub fn get_current_dir(&self) -> PathBuf {
         env::current_dir().unwrap_or_else(|_| self.work_dir.clone())
     }
-    
+
     pub async fn read_file(&self, path: &Path) -> Result<String> {
         let full_path = self.resolve_path(path);
-        
-        if !full_path.exists() {
-            return Err(anyhow!("File not found: {}", full_path.display()));
-        }
-        
-        let content = fs::read_to_string(&full_path).await?;
-        Ok(content)
+        self.fs.read_file(&full_path).await
     }
-    
+
     pub async fn write_file(&self, path: &Path, content: &str) -> Result<()> {
         let full_path = self.resolve_path(path);
-        
-        // Create parent directories if they don't exist
-        if let Some(parent) = full_path.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-        
-        fs::write(&full_path, content).await?;
+        self.fs.write_file(&full_path, content, WriteOptions::default()).await?;
         println!("✅ File written: {}", full_path.display());
         Ok(())
     }
-    
-    pub async fn edit_file(&self, ai_client: &OpenRouterClient, path: &Path, instructions: &str) -> Result<()> {
-        let full_path = self.resolve_path(path);
-        
-        if !full_path.exists() {
-            return Err(anyhow!("File not found: {}", full_path.display()));
+
+    /// Asks the model to rewrite `path` per `instructions`, prints a
+    /// unified diff between what's on disk and the model's proposal, and
+    /// writes it unless `dry_run` is set — giving the caller a reviewable
+    /// edit instead of a blind overwrite.
+    pub async fn edit_file(
+        &self,
+        ai_client: &OpenRouterClient,
+        path: &Path,
+        instructions: &str,
+        dry_run: bool,
+    ) -> Result<()> {
+        if !self.file_exists(path).await {
+            return Err(AgentError::FileNotFound(self.resolve_path(path)).into());
         }
-        
+
         let current_content = self.read_file(path).await?;
         let new_content = ai_client.edit_code(&current_content, instructions).await?;
-        
+
+        let diff = similar::TextDiff::from_lines(&current_content, &new_content);
+        print!(
+            "{}",
+            diff.unified_diff()
+                .context_radius(3)
+                .header(&path.display().to_string(), &path.display().to_string())
+        );
+
+        // Also show how the file has drifted from the last commit, if
+        // it's tracked — purely informational, doesn't change what gets
+        // written.
+        if let Some(head_content) = self.load_head_text(path)? {
+            if head_content != current_content {
+                let vs_head = similar::TextDiff::from_lines(&head_content, &new_content);
+                println!("--- vs last commit ---");
+                print!(
+                    "{}",
+                    vs_head
+                        .unified_diff()
+                        .context_radius(3)
+                        .header(&path.display().to_string(), &path.display().to_string())
+                );
+            }
+        }
+
+        if dry_run {
+            println!("(dry run, no changes written)");
+            return Ok(());
+        }
+
         self.write_file(path, &new_content).await?;
         Ok(())
     }
-    
-    pub async fn delete_path(&self, path: &Path) -> Result<()> {
+
+    /// Returns the blob content for `path` at the enclosing git
+    /// repository's HEAD, or `None` if there's no repository, no commit
+    /// yet, or the path isn't tracked there.
+    pub fn load_head_text(&self, path: &Path) -> Result<Option<String>> {
         let full_path = self.resolve_path(path);
-        
-        if !full_path.exists() {
-            return Err(anyhow!("Path not found: {}", full_path.display()));
+
+        let repo = match git2::Repository::discover(&full_path) {
+            Ok(repo) => repo,
+            Err(_) => return Ok(None),
+        };
+
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| anyhow!("repository at {} has no working directory", full_path.display()))?;
+        let relative = full_path.strip_prefix(workdir).unwrap_or(&full_path);
+
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(_) => return Ok(None),
+        };
+        let tree = head.peel_to_commit()?.tree()?;
+
+        let entry = match tree.get_path(relative) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+
+        let blob = repo.find_blob(entry.id())?;
+        Ok(Some(String::from_utf8_lossy(blob.content()).to_string()))
+    }
+
+    /// Applies `ops` as one unit: validates the whole plan up front
+    /// (sources exist, destinations don't collide, no path escapes
+    /// `work_dir`), snapshots every affected file's prior content into an
+    /// in-memory journal, then runs the ops in order. If any op fails,
+    /// every change made so far is rolled back from the journal, so a
+    /// bad step never leaves the tree half-modified — the same guarantee
+    /// an editor gives for a multi-file workspace edit.
+    pub async fn apply_plan(&self, ai_client: &OpenRouterClient, ops: Vec<FileOp>) -> Result<()> {
+        let mut destinations: HashSet<PathBuf> = HashSet::new();
+        let mut affected: Vec<PathBuf> = Vec::new();
+
+        for op in &ops {
+            match op {
+                FileOp::Create { path, .. } => {
+                    let full = self.guarded_path(path)?;
+                    if !destinations.insert(full.clone()) {
+                        return Err(anyhow!("plan writes {} more than once", path.display()));
+                    }
+                    affected.push(full);
+                }
+                FileOp::Edit { path, .. } => {
+                    let full = self.guarded_path(path)?;
+                    if !self.file_exists(path).await {
+                        return Err(AgentError::FileNotFound(full).into());
+                    }
+                    if !destinations.insert(full.clone()) {
+                        return Err(anyhow!("plan writes {} more than once", path.display()));
+                    }
+                    affected.push(full);
+                }
+                FileOp::Rename { from, to } => {
+                    let from_full = self.guarded_path(from)?;
+                    let to_full = self.guarded_path(to)?;
+                    if !self.file_exists(from).await {
+                        return Err(AgentError::FileNotFound(from_full).into());
+                    }
+                    if !destinations.insert(to_full.clone()) {
+                        return Err(anyhow!("plan writes {} more than once", to.display()));
+                    }
+                    affected.push(from_full);
+                    affected.push(to_full);
+                }
+                FileOp::Delete { path } => {
+                    let full = self.guarded_path(path)?;
+                    if !self.file_exists(path).await {
+                        return Err(AgentError::FileNotFound(full).into());
+                    }
+                    affected.push(full);
+                }
+            }
         }
-        
-        if full_path.is_dir() {
-            fs::remove_dir_all(&full_path).await?;
-        } else {
-            fs::remove_file(&full_path).await?;
+
+        let mut journal = Vec::with_capacity(affected.len());
+        for path in &affected {
+            let prior = self.fs.read_file(path).await.ok();
+            journal.push(JournalEntry { path: path.clone(), prior });
+        }
+
+        if let Err(err) = self.execute_plan(ai_client, &ops).await {
+            self.rollback(&journal).await;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    async fn execute_plan(&self, ai_client: &OpenRouterClient, ops: &[FileOp]) -> Result<()> {
+        for op in ops {
+            match op {
+                FileOp::Create { path, contents } => self.write_file(path, contents).await?,
+                FileOp::Edit { path, instructions } => self.edit_file(ai_client, path, instructions, false).await?,
+                FileOp::Rename { from, to } => self.move_file(from, to).await?,
+                FileOp::Delete { path } => self.delete_path(path).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores every journaled path to its pre-plan state, in reverse
+    /// order: paths that had content get it written back, paths that
+    /// didn't exist yet get removed again, undoing creates and renames
+    /// alike since a rename's `to` simply has no prior content.
+    async fn rollback(&self, journal: &[JournalEntry]) {
+        for entry in journal.iter().rev() {
+            match &entry.prior {
+                Some(content) => {
+                    let options = WriteOptions { atomic: true, preserve_line_endings: false };
+                    let _ = self.fs.write_file(&entry.path, content, options).await;
+                }
+                None => {
+                    let _ = self
+                        .fs
+                        .delete_path(&entry.path, RemoveOptions { recursive: false, ignore_if_not_exists: true })
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Resolves `path` and rejects it if it normalizes to somewhere
+    /// outside `work_dir`, so a plan can't `../`-escape the sandbox.
+    fn guarded_path(&self, path: &Path) -> Result<PathBuf> {
+        let full = normalize_path(&self.resolve_path(path));
+        let root = normalize_path(&self.work_dir);
+        if !full.starts_with(&root) {
+            return Err(anyhow!("path escapes work directory: {}", path.display()));
         }
-        
+        Ok(full)
+    }
+
+    pub async fn delete_path(&self, path: &Path) -> Result<()> {
+        let full_path = self.resolve_path(path);
+        self.fs.delete_path(&full_path, RemoveOptions { recursive: true, ignore_if_not_exists: false }).await?;
         println!("✅ Deleted: {}", full_path.display());
         Ok(())
     }
-    
-    pub async fn list_directory(&self, path: &Path) -> Result<Vec<String>> {
+
+    /// Lists `path`'s immediate children, filtered by `options`.
+    pub async fn list_directory(&self, path: &Path, options: &SearchOptions) -> Result<Vec<String>> {
         let full_path = self.resolve_path(path);
-        
-        if !full_path.exists() {
+        if !full_path.exists() || !full_path.is_dir() {
             return Err(anyhow!("Directory not found: {}", full_path.display()));
         }
-        
-        if !full_path.is_dir() {
-            return Err(anyhow!("Path is not a directory: {}", full_path.display()));
-        }
-        
-        let mut entries = Vec::new();
-        let mut dir = fs::read_dir(&full_path).await?;
-        
-        while let Some(entry) = dir.next_entry().await? {
-            let path = entry.path();
-            let name = path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("Unknown")
-                .to_string();
-            
-            let metadata = entry.metadata().await?;
-            let file_type = if metadata.is_dir() {
-                "DIR"
-            } else if metadata.is_file() {
-                "FILE"
-            } else {
-                "OTHER"
-            };
-            
-            entries.push(format!("{} [{}]", name, file_type));
-        }
-        
-        entries.sort();
-        Ok(entries)
+        walk_matching(&full_path, options, Some(1))
     }
-    
+
     pub async fn copy_file(&self, src: &Path, dst: &Path) -> Result<()> {
         let src_path = self.resolve_path(src);
         let dst_path = self.resolve_path(dst);
-        
-        if !src_path.exists() {
-            return Err(anyhow!("Source file not found: {}", src_path.display()));
-        }
-        
-        if let Some(parent) = dst_path.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-        
-        fs::copy(&src_path, &dst_path).await?;
+        self.fs.copy_file(&src_path, &dst_path, CopyOptions { overwrite: true, ignore_if_exists: false }).await?;
         println!("✅ Copied: {} -> {}", src_path.display(), dst_path.display());
         Ok(())
     }
-    
+
     pub async fn move_file(&self, src: &Path, dst: &Path) -> Result<()> {
         let src_path = self.resolve_path(src);
         let dst_path = self.resolve_path(dst);
-        
-        if !src_path.exists() {
-            return Err(anyhow!("Source file not found: {}", src_path.display()));
-        }
-        
-        if let Some(parent) = dst_path.parent() {
-            fs::create_dir_all(parent).await?;
-        }
-        
-        fs::rename(&src_path, &dst_path).await?;
+        self.fs.move_file(&src_path, &dst_path, RenameOptions { overwrite: true, ignore_if_exists: false }).await?;
         println!("✅ Moved: {} -> {}", src_path.display(), dst_path.display());
         Ok(())
     }
-    
+
     pub async fn create_directory(&self, path: &Path) -> Result<()> {
         let full_path = self.resolve_path(path);
-        fs::create_dir_all(&full_path).await?;
+        self.fs.create_directory(&full_path, CreateOptions { overwrite: false, ignore_if_exists: true }).await?;
         println!("✅ Directory created: {}", full_path.display());
         Ok(())
     }
-    
+
     pub async fn file_exists(&self, path: &Path) -> bool {
         let full_path = self.resolve_path(path);
-        full_path.exists()
+        self.fs.metadata(&full_path).await.is_ok()
     }
-    
+
     pub async fn get_file_info(&self, path: &Path) -> Result<String> {
         let full_path = self.resolve_path(path);
-        
-        if !full_path.exists() {
-            return Err(anyhow!("File not found: {}", full_path.display()));
-        }
-        
-        let metadata = fs::metadata(&full_path).await?;
-        let file_type = if metadata.is_dir() {
+        let metadata = self.fs.metadata(&full_path).await?;
+
+        let file_type = if metadata.is_dir {
             "Directory"
-        } else if metadata.is_file() {
+        } else if metadata.is_file {
             "File"
         } else {
             "Other"
         };
-        
-        let size = metadata.len();
-        let modified = metadata.modified()
-            .map(|t| format!("{:?}", t))
-            .unwrap_or_else(|_| "Unknown".to_string());
-        
+
+        let modified = metadata.modified
+            .map(|secs| secs.to_string())
+            .unwrap_or_else(|| "Unknown".to_string());
+
         Ok(format!(
             "Path: {}\nType: {}\nSize: {} bytes\nModified: {}",
             full_path.display(),
             file_type,
-            size,
+            metadata.size,
             modified
         ))
     }
-    
-    pub async fn search_files(&self, pattern: &str, directory: &Path) -> Result<Vec<String>> {
+
+    /// Recursively walks `directory`, filtered by `options`.
+    pub async fn search_files(&self, directory: &Path, options: &SearchOptions) -> Result<Vec<String>> {
         let full_path = self.resolve_path(directory);
-        
+
         if !full_path.exists() || !full_path.is_dir() {
             return Err(anyhow!("Directory not found: {}", full_path.display()));
         }
-        
-        let mut matches = Vec::new();
-        
-        for entry in WalkDir::new(&full_path).into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            
-            if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                if file_name.to_lowercase().contains(&pattern.to_lowercase()) {
-                    matches.push(path.display().to_string());
+
+        walk_matching(&full_path, options, None)
+    }
+
+    /// Watches `path` for filesystem changes and yields debounced
+    /// batches: events for the same path arriving within
+    /// `WATCH_DEBOUNCE_WINDOW` of each other are coalesced, with a
+    /// create-then-modify collapsing to `Created` and any sequence
+    /// ending in removal collapsing to `Removed`.
+    pub fn watch(&self, path: &Path) -> impl Stream<Item = Vec<FileEvent>> {
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
+        let path = self.resolve_path(path);
+
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = raw_tx.send(res);
+        })
+        .expect("failed to create file watcher");
+        watcher
+            .watch(&path, RecursiveMode::Recursive)
+            .expect("failed to watch path");
+
+        let (batch_tx, batch_rx) = mpsc::unbounded_channel::<Vec<FileEvent>>();
+
+        tokio::spawn(async move {
+            // Keep the watcher alive for as long as this task runs; it
+            // stops emitting once dropped.
+            let _watcher = watcher;
+            let mut pending: HashMap<PathBuf, FileEventKind> = HashMap::new();
+
+            while let Some(event) = raw_rx.recv().await {
+                merge_event(&mut pending, event);
+
+                loop {
+                    match tokio::time::timeout(WATCH_DEBOUNCE_WINDOW, raw_rx.recv()).await {
+                        Ok(Some(event)) => merge_event(&mut pending, event),
+                        Ok(None) => break,
+                        Err(_) => break,
+                    }
+                }
+
+                if pending.is_empty() {
+                    continue;
+                }
+
+                let batch: Vec<FileEvent> = pending
+                    .drain()
+                    .map(|(path, kind)| FileEvent { path, kind })
+                    .collect();
+
+                if batch_tx.send(batch).is_err() {
+                    break;
                 }
             }
-        }
-        
-        Ok(matches)
+        });
+
+        UnboundedReceiverStream::new(batch_rx)
     }
-    
+
     fn resolve_path(&self, path: &Path) -> PathBuf {
         if path.is_absolute() {
             path.to_path_buf()
@@ -218,4 +1012,151 @@ impl FileManager {
             self.work_dir.join(path)
         }
     }
-}
\ No newline at end of file
+}
+
+const WATCH_DEBOUNCE_WINDOW: Duration = Duration::from_millis(75);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileEventKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileEvent {
+    pub path: PathBuf,
+    pub kind: FileEventKind,
+}
+
+/// A single mutation within an `apply_plan` batch. Tagged the same way as
+/// `main.rs`'s `ToolCall` so a model-proposed plan deserializes straight
+/// off the wire, e.g. `{"op":"create","path":"...","contents":"..."}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FileOp {
+    Create { path: PathBuf, contents: String },
+    Edit { path: PathBuf, instructions: String },
+    Rename { from: PathBuf, to: PathBuf },
+    Delete { path: PathBuf },
+}
+
+/// A path's content before `apply_plan` touched it, or `None` if it
+/// didn't exist yet — enough to undo a create, edit, delete, or rename.
+struct JournalEntry {
+    path: PathBuf,
+    prior: Option<String>,
+}
+
+/// Lexically collapses `.` and `..` components without touching the
+/// filesystem (no symlink resolution), so `guarded_path` can compare a
+/// plan's target against `work_dir` even for paths that don't exist yet.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            std::path::Component::CurDir => {}
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+fn merge_event(pending: &mut HashMap<PathBuf, FileEventKind>, event: notify::Result<Event>) {
+    let Ok(event) = event else {
+        return;
+    };
+
+    let kind = match event.kind {
+        EventKind::Create(_) => FileEventKind::Created,
+        EventKind::Modify(_) => FileEventKind::Modified,
+        EventKind::Remove(_) => FileEventKind::Removed,
+        _ => return,
+    };
+
+    for path in event.paths {
+        pending
+            .entry(path)
+            .and_modify(|existing| {
+                *existing = match (&existing, &kind) {
+                    (FileEventKind::Created, FileEventKind::Modified) => FileEventKind::Created,
+                    (_, FileEventKind::Removed) => FileEventKind::Removed,
+                    _ => kind.clone(),
+                };
+            })
+            .or_insert_with(|| kind.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> FileManager {
+        FileManager::new(Path::new("/work"), Arc::new(FakeFs::new()))
+    }
+
+    #[tokio::test]
+    async fn fake_fs_write_then_read_round_trips() {
+        let manager = manager();
+        manager.write_file(Path::new("notes.txt"), "hello").await.unwrap();
+        let content = manager.read_file(Path::new("notes.txt")).await.unwrap();
+        assert_eq!(content, "hello");
+    }
+
+    #[tokio::test]
+    async fn apply_plan_rolls_back_on_failure() {
+        let manager = manager();
+        manager.write_file(Path::new("a.txt"), "hello").await.unwrap();
+        let ai_client = OpenRouterClient::new("test-key", "test-model", 1024);
+
+        let ops = vec![
+            FileOp::Delete { path: PathBuf::from("a.txt") },
+            FileOp::Delete { path: PathBuf::from("a.txt") },
+        ];
+
+        let result = manager.apply_plan(&ai_client, ops).await;
+        assert!(result.is_err());
+
+        let content = manager.read_file(Path::new("a.txt")).await.unwrap();
+        assert_eq!(content, "hello");
+    }
+
+    #[test]
+    fn reencode_to_match_preserves_crlf_and_trailing_newline() {
+        let existing = "line1\r\nline2\r\n";
+        let new_content = "line1\nline2\nline3";
+        assert_eq!(reencode_to_match(existing, new_content), "line1\r\nline2\r\nline3\r\n");
+    }
+
+    #[test]
+    fn reencode_to_match_drops_trailing_newline_when_existing_has_none() {
+        let existing = "line1\nline2";
+        let new_content = "line1\nline2\nline3\n";
+        assert_eq!(reencode_to_match(existing, new_content), "line1\nline2\nline3");
+    }
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn merge_event_collapses_create_then_modify_to_created() {
+        let mut pending = HashMap::new();
+        merge_event(&mut pending, event(EventKind::Create(notify::event::CreateKind::Any), "a.txt"));
+        merge_event(&mut pending, event(EventKind::Modify(notify::event::ModifyKind::Any), "a.txt"));
+        assert_eq!(pending.get(Path::new("a.txt")), Some(&FileEventKind::Created));
+    }
+
+    #[test]
+    fn merge_event_collapses_any_sequence_ending_in_remove() {
+        let mut pending = HashMap::new();
+        merge_event(&mut pending, event(EventKind::Create(notify::event::CreateKind::Any), "b.txt"));
+        merge_event(&mut pending, event(EventKind::Modify(notify::event::ModifyKind::Any), "b.txt"));
+        merge_event(&mut pending, event(EventKind::Remove(notify::event::RemoveKind::Any), "b.txt"));
+        assert_eq!(pending.get(Path::new("b.txt")), Some(&FileEventKind::Removed));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::config::RemoteConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+
+#[derive(Debug, Clone)]
+pub struct DirEntryInfo {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileMetadata {
+    pub len: u64,
+    pub is_dir: bool,
+    pub is_file: bool,
+}
+
+/// Abstracts the primitive operations `utils`'s file-editing helpers and
+/// `Config::backup_file` build on, so the same call sites work whether
+/// `work_dir` lives on this machine (`LocalFs`) or on a remote host
+/// reached over SFTP (`SftpFs`).
+#[async_trait]
+pub trait FileSystem: Send + Sync {
+    async fn read_to_string(&self, path: &Path) -> Result<String>;
+    async fn write(&self, path: &Path, content: &[u8]) -> Result<()>;
+    async fn append(&self, path: &Path, content: &[u8]) -> Result<()>;
+    async fn read_dir(&self, path: &Path) -> Result<Vec<DirEntryInfo>>;
+    async fn metadata(&self, path: &Path) -> Result<FileMetadata>;
+    async fn copy(&self, src: &Path, dst: &Path) -> Result<()>;
+    async fn create_dir_all(&self, path: &Path) -> Result<()>;
+    async fn exists(&self, path: &Path) -> bool;
+    async fn remove_file(&self, path: &Path) -> Result<()>;
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Writes `content` to a temp file beside `path` and renames it into
+    /// place, so a reader never observes a partially-written file and a
+    /// crash mid-write leaves the original untouched.
+    async fn write_atomic(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let tmp_path = temp_sibling_path(path);
+        self.write(&tmp_path, content).await?;
+        self.rename(&tmp_path, path).await
+    }
+}
+
+fn temp_sibling_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("tmp");
+    path.with_file_name(format!(".{}.tmp-{}", file_name, uuid::Uuid::new_v4()))
+}
+
+/// Runs file operations against this machine via `tokio::fs`.
+pub struct LocalFs;
+
+#[async_trait]
+impl FileSystem for LocalFs {
+    async fn read_to_string(&self, path: &Path) -> Result<String> {
+        Ok(fs::read_to_string(path).await?)
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        Ok(fs::write(path, content).await?)
+    }
+
+    async fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        file.write_all(content).await?;
+        Ok(())
+    }
+
+    async fn read_dir(&self, path: &Path) -> Result<Vec<DirEntryInfo>> {
+        let mut entries = fs::read_dir(path).await?;
+        let mut out = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            out.push(DirEntryInfo {
+                name: entry.file_name().to_string_lossy().to_string(),
+                is_dir: entry.file_type().await?.is_dir(),
+            });
+        }
+        Ok(out)
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<FileMetadata> {
+        let metadata = fs::metadata(path).await?;
+        Ok(FileMetadata {
+            len: metadata.len(),
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+        })
+    }
+
+    async fn copy(&self, src: &Path, dst: &Path) -> Result<()> {
+        fs::copy(src, dst).await?;
+        Ok(())
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path).await?;
+        Ok(())
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        fs::metadata(path).await.is_ok()
+    }
+
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to).await?;
+        Ok(())
+    }
+}
+
+/// Speaks SFTP to a host configured via `Config`'s `remote` section.
+/// `ssh2` is a blocking library, so every call is dispatched onto a
+/// blocking-pool thread with `tokio::task::spawn_blocking`; the session
+/// is kept behind a `Mutex` since a libssh2 session isn't safe to drive
+/// from more than one thread at a time.
+pub struct SftpFs {
+    session: Arc<Mutex<ssh2::Session>>,
+    root_dir: PathBuf,
+}
+
+impl SftpFs {
+    pub fn connect(remote: &RemoteConfig) -> Result<Self> {
+        let tcp = TcpStream::connect((remote.host.as_str(), remote.port))?;
+        let mut session = ssh2::Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        session.userauth_pubkey_file(&remote.user, None, &remote.key_path, None)?;
+
+        if !session.authenticated() {
+            return Err(anyhow!("SFTP authentication failed for {}@{}", remote.user, remote.host));
+        }
+
+        Ok(Self {
+            session: Arc::new(Mutex::new(session)),
+            root_dir: remote.root_dir.clone(),
+        })
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root_dir.join(path)
+        }
+    }
+}
+
+#[async_trait]
+impl FileSystem for SftpFs {
+    async fn read_to_string(&self, path: &Path) -> Result<String> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || -> Result<String> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            let mut file = sftp.open(&path)?;
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            Ok(content)
+        })
+        .await?
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        let content = content.to_vec();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            let mut file = sftp.create(&path)?;
+            file.write_all(&content)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn append(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        let content = content.to_vec();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            let mut existing = match sftp.open(&path) {
+                Ok(mut file) => {
+                    let mut buf = Vec::new();
+                    file.read_to_end(&mut buf)?;
+                    buf
+                }
+                Err(_) => Vec::new(),
+            };
+            existing.extend_from_slice(&content);
+            let mut file = sftp.create(&path)?;
+            file.write_all(&existing)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn read_dir(&self, path: &Path) -> Result<Vec<DirEntryInfo>> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || -> Result<Vec<DirEntryInfo>> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            Ok(sftp
+                .readdir(&path)?
+                .into_iter()
+                .map(|(entry_path, stat)| DirEntryInfo {
+                    name: entry_path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("")
+                        .to_string(),
+                    is_dir: stat.is_dir(),
+                })
+                .collect())
+        })
+        .await?
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<FileMetadata> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || -> Result<FileMetadata> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            let stat = sftp.stat(&path)?;
+            Ok(FileMetadata {
+                len: stat.size.unwrap_or(0),
+                is_dir: stat.is_dir(),
+                is_file: stat.is_file(),
+            })
+        })
+        .await?
+    }
+
+    async fn copy(&self, src: &Path, dst: &Path) -> Result<()> {
+        let content = self.read_to_string(src).await?;
+        self.write(dst, content.as_bytes()).await
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            let mut current = PathBuf::new();
+            for component in path.components() {
+                current.push(component);
+                if sftp.stat(&current).is_err() {
+                    let _ = sftp.mkdir(&current, 0o755);
+                }
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || {
+            let session = session.lock().unwrap();
+            session.sftp().and_then(|sftp| sftp.stat(&path)).is_ok()
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        let session = self.session.clone();
+        let path = self.resolve(path);
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            sftp.unlink(&path)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let session = self.session.clone();
+        let from = self.resolve(from);
+        let to = self.resolve(to);
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let session = session.lock().unwrap();
+            let sftp = session.sftp()?;
+            sftp.rename(&from, &to, None)?;
+            Ok(())
+        })
+        .await?
+    }
+}
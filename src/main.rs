@@ -1,19 +1,32 @@
 use anyhow::{Result, anyhow};
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use std::env;
 
 mod ai;
+mod errors;
+mod file_index;
 mod file_ops;
+mod filesystem;
 mod terminal;
 mod config;
+mod search_index;
+mod shell_session;
+mod tokenizer;
 mod utils;
 
 use ai::OpenRouterClient;
-use file_ops::FileManager;
-use terminal::TerminalManager;
+use errors::AgentError;
+use file_ops::{FileManager, RealFs};
+use filesystem::{FileSystem, LocalFs, SftpFs};
+use terminal::{CommandSpec, StreamLine, TerminalManager};
 use config::Config;
+use shell_session::{ShellCompleter, ShellSession};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser)]
 #[command(name = "ai-cli-agent")]
@@ -42,6 +55,16 @@ enum Commands {
     Execute {
         #[arg(help = "Task description")]
         task: String,
+        /// Print the model's reply as it streams in instead of waiting
+        /// for the full response
+        #[arg(long)]
+        stream: bool,
+    },
+    /// Execute a task via OpenAI-style function calling instead of the
+    /// JSON tool-call protocol `execute` uses
+    ExecuteWithTools {
+        #[arg(help = "Task description")]
+        task: String,
     },
     /// Read file content
     Read {
@@ -61,6 +84,9 @@ enum Commands {
         path: PathBuf,
         #[arg(help = "Edit instructions")]
         instructions: String,
+        /// Show the proposed diff without writing it
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Delete file or directory
     Delete {
@@ -76,9 +102,53 @@ enum Commands {
     Run {
         #[arg(help = "Command to execute")]
         command: String,
+        #[arg(long, help = "Run through the system shell instead of a direct argv spawn")]
+        shell: bool,
     },
     /// Interactive mode
     Interactive,
+    /// List backups of a file, newest first
+    BackupList {
+        #[arg(help = "File path")]
+        path: PathBuf,
+    },
+    /// Restore a file from a specific backup
+    BackupRestore {
+        #[arg(help = "Backup file path")]
+        backup_path: PathBuf,
+        #[arg(help = "File path to restore into")]
+        target: PathBuf,
+    },
+    /// Delete backups beyond the retention policy
+    BackupPrune,
+    /// Rebuild the semantic search index over the work directory
+    Reindex,
+    /// Query the semantic search index for relevant snippets
+    SemanticSearch {
+        #[arg(help = "Query text")]
+        query: String,
+        #[arg(long, default_value_t = 5)]
+        top_k: usize,
+    },
+    /// List background jobs spawned via the agent
+    Jobs,
+    /// Kill a background job by id
+    Kill {
+        #[arg(help = "Job id")]
+        id: u64,
+    },
+    /// Watch a path for filesystem changes and print debounced events
+    Watch {
+        #[arg(help = "Path to watch")]
+        path: PathBuf,
+    },
+    /// Find groups of files with identical content under the work directory
+    FindDuplicates,
+    /// Grep indexed file contents for a substring
+    IndexSearch {
+        #[arg(help = "Text to search for")]
+        needle: String,
+    },
 }
 
 #[tokio::main]
@@ -105,152 +175,572 @@ async fn main() -> Result<()> {
     }
     
     // Initialize managers
-    let ai_client = OpenRouterClient::new(&config.openrouter_api_key, &config.model);
-    let file_manager = FileManager::new(&config.work_dir);
+    let ai_client = OpenRouterClient::new(&config.openrouter_api_key, &config.model, config.max_tokens);
+    let file_manager = FileManager::new(&config.work_dir, file_manager_fs(&config)?);
     let terminal_manager = TerminalManager::new();
     
     // Execute command
     match cli.command {
-        Commands::Execute { task } => {
-            let prompt = cli.prompt.unwrap_or_else(|| 
+        Commands::Execute { task, stream } => {
+            let prompt = cli.prompt.unwrap_or_else(||
                 format!("You are an AI software development assistant. Execute the following task: {}", task)
             );
-            execute_task(&ai_client, &file_manager, &terminal_manager, &prompt, &task).await?;
+            execute_task(&ai_client, &file_manager, &terminal_manager, &prompt, &task, &config.index_dir, stream).await?;
+        }
+        Commands::ExecuteWithTools { task } => {
+            let prompt = cli.prompt.unwrap_or_else(|| {
+                "You are an AI software development assistant. Use the available tools to complete the user's task.".to_string()
+            });
+            let registry = build_tool_registry(&config);
+            let response = ai_client.send_message_with_tools(&prompt, &task, &registry, 8).await?;
+            println!("{}", response);
         }
         Commands::Read { path } => {
             let content = file_manager.read_file(&path).await?;
             println!("{}", content);
         }
         Commands::Write { path, content } => {
+            backup_before_overwrite(&config, &path).await?;
             file_manager.write_file(&path, &content).await?;
             println!("File written successfully: {}", path.display());
         }
-        Commands::Edit { path, instructions } => {
-            file_manager.edit_file(&ai_client, &path, &instructions).await?;
-            println!("File edited successfully: {}", path.display());
+        Commands::Edit { path, instructions, dry_run } => {
+            if !dry_run {
+                backup_before_overwrite(&config, &path).await?;
+            }
+            file_manager.edit_file(&ai_client, &path, &instructions, dry_run).await?;
+            if !dry_run {
+                println!("File edited successfully: {}", path.display());
+            }
         }
         Commands::Delete { path } => {
+            backup_before_overwrite(&config, &path).await?;
             file_manager.delete_path(&path).await?;
             println!("Deleted successfully: {}", path.display());
         }
         Commands::List { path } => {
             let path = path.unwrap_or_else(|| PathBuf::from("."));
-            let contents = file_manager.list_directory(&path).await?;
+            let contents = file_manager.list_directory(&path, &file_ops::SearchOptions::default()).await?;
             for item in contents {
                 println!("{}", item);
             }
         }
-        Commands::Run { command } => {
-            let output = terminal_manager.execute_command(&command).await?;
+        Commands::Run { command, shell } => {
+            let output = if shell {
+                terminal_manager.execute_command_in_shell(&command).await?
+            } else {
+                terminal_manager.execute_command(&command).await?
+            };
             println!("{}", output);
         }
         Commands::Interactive => {
             interactive_mode(&ai_client, &file_manager, &terminal_manager).await?;
         }
+        Commands::BackupList { path } => {
+            let fs = backup_fs(&config)?;
+            for entry in config.list_backups(fs.as_ref(), &path).await? {
+                println!("{}  {}", entry.timestamp, entry.path.display());
+            }
+        }
+        Commands::BackupRestore { backup_path, target } => {
+            let fs = backup_fs(&config)?;
+            config.restore_backup(fs.as_ref(), &backup_path, &target).await?;
+        }
+        Commands::BackupPrune => {
+            let fs = backup_fs(&config)?;
+            let deleted = config
+                .prune_backups(fs.as_ref(), config.max_backups_per_file, config.max_backup_age_days)
+                .await?;
+            println!("Pruned {} backup(s)", deleted);
+        }
+        Commands::Reindex => {
+            let mut index = search_index::SearchIndex::load(config.index_dir.clone()).await?;
+            index.reindex(&ai_client, &config.work_dir).await?;
+            println!("Semantic index refreshed");
+        }
+        Commands::SemanticSearch { query, top_k } => {
+            let index = search_index::SearchIndex::load(config.index_dir.clone()).await?;
+            for chunk in index.query(&ai_client, &query, top_k).await? {
+                println!("{} L{}-{}", chunk.path.display(), chunk.line_range.0, chunk.line_range.1);
+            }
+        }
+        Commands::Jobs => {
+            for job in terminal_manager.list_jobs().await {
+                println!("{}", format_job(&job));
+            }
+        }
+        Commands::Kill { id } => {
+            terminal_manager.kill_job(id).await?;
+            println!("Killed job {}", id);
+        }
+        Commands::Watch { path } => {
+            println!("Watching {} for changes (Ctrl+C to stop)...", path.display());
+            let mut batches = Box::pin(file_manager.watch(&path));
+            while let Some(batch) = batches.next().await {
+                for event in batch {
+                    println!("{:?} {}", event.kind, event.path.display());
+                }
+            }
+        }
+        Commands::FindDuplicates => {
+            let mut index = file_index::FileIndex::new(&config.work_dir);
+            index.refresh().await?;
+            for group in index.find_duplicates() {
+                println!("{}", group.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join("  =  "));
+            }
+        }
+        Commands::IndexSearch { needle } => {
+            let mut index = file_index::FileIndex::new(&config.work_dir);
+            index.refresh().await?;
+            for path in index.search_by_content(&needle).await? {
+                println!("{}", path.display());
+            }
+        }
     }
     
     Ok(())
 }
 
+fn format_job(job: &terminal::JobInfo) -> String {
+    let status = match job.status {
+        terminal::JobStatus::Running => "running".to_string(),
+        terminal::JobStatus::Exited(code) => format!("exited({})", code),
+    };
+    format!("[{}] pid={} {} — {}", job.id, job.pid, status, job.command)
+}
+
+/// Maximum number of tool-call round trips before we give up on a task.
+const MAX_AGENT_TURNS: u32 = 8;
+
+/// How long a single `RunCommand` action is allowed to run before we kill
+/// it and report a timeout back to the model.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Picks the `FileSystem` backend the backup commands run against: SFTP
+/// to `config.remote`'s host when configured, otherwise this machine.
+fn backup_fs(config: &Config) -> Result<std::sync::Arc<dyn FileSystem>> {
+    match &config.remote {
+        Some(remote) => Ok(std::sync::Arc::new(SftpFs::connect(remote)?)),
+        None => Ok(std::sync::Arc::new(LocalFs)),
+    }
+}
+
+/// Picks the `Fs` backend `FileManager` reads/writes/edits through:
+/// `config.remote`'s host over SFTP when configured (via `FsAdapter`,
+/// which lets any `FileSystem` back the richer `Fs` trait), otherwise
+/// this machine. This is the same selection `backup_fs` makes for the
+/// backup subsystem, applied to the general file I/O path so `SftpFs`
+/// isn't backup-only.
+fn file_manager_fs(config: &Config) -> Result<std::sync::Arc<dyn file_ops::Fs>> {
+    match &config.remote {
+        Some(remote) => Ok(std::sync::Arc::new(file_ops::FsAdapter(SftpFs::connect(remote)?))),
+        None => Ok(std::sync::Arc::new(RealFs)),
+    }
+}
+
+/// Snapshots `path` into `config.backup_dir` before a destructive CLI
+/// command overwrites or removes it, so `backup-list`/`backup-restore`
+/// have something to work with. No-op if the file doesn't exist yet
+/// (nothing to protect) or backups are disabled.
+async fn backup_before_overwrite(config: &Config, path: &Path) -> Result<()> {
+    let resolved = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        config.work_dir.join(path)
+    };
+
+    let fs = backup_fs(config)?;
+    if fs.exists(&resolved).await {
+        config.backup_file(fs.as_ref(), &resolved).await?;
+    }
+    Ok(())
+}
+
+/// Builds the `ToolRegistry` backing `execute-with-tools`: a small subset
+/// of the file operations `dispatch_tool_call` offers through the JSON
+/// protocol, exposed instead via OpenAI-style function calling so
+/// `send_message_with_tools` has something real to drive.
+fn build_tool_registry(config: &Config) -> ai::ToolRegistry {
+    let fm = std::sync::Arc::new(FileManager::new(&config.work_dir, std::sync::Arc::new(RealFs)));
+    let mut registry = ai::ToolRegistry::new();
+
+    {
+        let fm = fm.clone();
+        registry.register(
+            ai::ToolDefinition::new(
+                "read_file",
+                "Read a file's contents",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": { "path": { "type": "string" } },
+                    "required": ["path"]
+                }),
+            ),
+            move |args| {
+                let fm = fm.clone();
+                async move {
+                    let path = args
+                        .get("path")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("missing `path`"))?;
+                    fm.read_file(Path::new(path)).await
+                }
+            },
+        );
+    }
+
+    {
+        let fm = fm.clone();
+        registry.register(
+            ai::ToolDefinition::new(
+                "write_file",
+                "Write content to a file",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "content": { "type": "string" }
+                    },
+                    "required": ["path", "content"]
+                }),
+            ),
+            move |args| {
+                let fm = fm.clone();
+                async move {
+                    let path = args
+                        .get("path")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("missing `path`"))?;
+                    let content = args
+                        .get("content")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("missing `content`"))?;
+                    fm.write_file(Path::new(path), content).await.map(|_| String::new())
+                }
+            },
+        );
+    }
+
+    {
+        let fm = fm.clone();
+        registry.register(
+            ai::ToolDefinition::new(
+                "list_directory",
+                "List a directory's contents",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": { "path": { "type": "string" } },
+                    "required": []
+                }),
+            ),
+            move |args| {
+                let fm = fm.clone();
+                async move {
+                    let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
+                    let entries = fm
+                        .list_directory(Path::new(path), &file_ops::SearchOptions::default())
+                        .await?;
+                    Ok(entries.join("\n"))
+                }
+            },
+        );
+    }
+
+    registry
+}
+
 async fn execute_task(
     ai_client: &OpenRouterClient,
     file_manager: &FileManager,
     terminal_manager: &TerminalManager,
     system_prompt: &str,
     task: &str,
+    index_dir: &Path,
+    stream: bool,
 ) -> Result<()> {
     println!("🤖 AI CLI Agent - Executing task: {}", task);
     println!("=====================================");
-    
-    let context = format!(
+
+    let preamble = format!(
         "Current working directory: {}\n\nTask: {}",
         file_manager.get_current_dir().display(),
         task
     );
-    
-    let response = ai_client.send_message(system_prompt, &context).await?;
-    
-    // Parse and execute the AI's response
-    let actions = parse_ai_response(&response);
-    
-    for action in actions {
-        match action {
-            AIAction::WriteFile { path, content } => {
-                println!("📝 Writing file: {}", path);
-                file_manager.write_file(&PathBuf::from(path), &content).await?;
-            }
-            AIAction::EditFile { path, instructions } => {
-                println!("✏️ Editing file: {}", path);
-                file_manager.edit_file(ai_client, &PathBuf::from(path), &instructions).await?;
-            }
-            AIAction::RunCommand { command } => {
-                println!("⚡ Running command: {}", command);
-                let output = terminal_manager.execute_command(&command).await?;
-                println!("Output: {}", output);
-            }
-            AIAction::ReadFile { path } => {
-                println!("📖 Reading file: {}", path);
-                let content = file_manager.read_file(&PathBuf::from(path)).await?;
-                println!("Content: {}", content);
-            }
-            AIAction::DeleteFile { path } => {
-                println!("🗑️ Deleting file: {}", path);
-                file_manager.delete_path(&PathBuf::from(path)).await?;
-            }
-            AIAction::CreateDirectory { path } => {
-                println!("📁 Creating directory: {}", path);
-                fs::create_dir_all(&path).await?;
-            }
-            AIAction::ListDirectory { path } => {
-                println!("📋 Listing directory: {}", path);
-                let contents = file_manager.list_directory(&PathBuf::from(path)).await?;
-                for item in contents {
-                    println!("  {}", item);
+    let mut history = String::new();
+
+    for turn in 1..=MAX_AGENT_TURNS {
+        let context = if history.is_empty() {
+            preamble.clone()
+        } else {
+            format!("{}\n\n{}", preamble, history)
+        };
+
+        let response = if stream {
+            ai_client
+                .send_message_stream(system_prompt, &context, |token| {
+                    print!("{}", token);
+                    let _ = std::io::Write::flush(&mut std::io::stdout());
+                })
+                .await?
+        } else {
+            // Pull the task-relevant snippets the semantic index already
+            // has on hand (if it's been built via `reindex`) and let
+            // `fit_context` decide how much of them the model's window
+            // can actually hold; an empty/never-built index just yields
+            // no extra context.
+            let context_chunks = if turn == 1 {
+                match search_index::SearchIndex::load(index_dir.to_path_buf()).await {
+                    Ok(index) => match index.query(ai_client, task, 5).await {
+                        Ok(matches) => search_index::to_context_chunks(&matches).await.unwrap_or_default(),
+                        Err(_) => Vec::new(),
+                    },
+                    Err(_) => Vec::new(),
+                }
+            } else {
+                Vec::new()
+            };
+            ai_client.send_message_with_context(system_prompt, &context, &context_chunks).await?
+        };
+
+        let calls = match parse_tool_calls(&response) {
+            Ok(calls) => calls,
+            Err(e) => {
+                let retryable = e
+                    .downcast_ref::<AgentError>()
+                    .map(AgentError::is_retryable)
+                    .unwrap_or(true);
+                let results = vec![ToolResult {
+                    id: String::new(),
+                    tool: "parse_tool_calls".to_string(),
+                    success: false,
+                    output: e.to_string(),
+                    retryable,
+                }];
+                history.push_str(&format!(
+                    "Tool results from turn {}:\n{}\n\n",
+                    turn,
+                    serde_json::to_string_pretty(&results)?
+                ));
+                if retryable {
+                    continue;
+                } else {
+                    break;
                 }
             }
+        };
+        if calls.is_empty() {
+            break;
+        }
+
+        let mut results = Vec::new();
+        for envelope in calls {
+            let result = dispatch_tool_call(&envelope, ai_client, file_manager, terminal_manager).await;
+            results.push(result);
+        }
+
+        let all_succeeded = results.iter().all(|r| r.success);
+        let hit_unretryable = results.iter().any(|r| !r.success && !r.retryable);
+
+        history.push_str(&format!(
+            "Tool results from turn {}:\n{}\n\n",
+            turn,
+            serde_json::to_string_pretty(&results)?
+        ));
+
+        if all_succeeded || hit_unretryable {
+            break;
         }
     }
-    
+
     println!("✅ Task completed successfully!");
     Ok(())
 }
 
+async fn dispatch_tool_call(
+    envelope: &ToolCallEnvelope,
+    ai_client: &OpenRouterClient,
+    file_manager: &FileManager,
+    terminal_manager: &TerminalManager,
+) -> ToolResult {
+    let (tool, outcome) = match &envelope.call {
+        ToolCall::WriteFile { path, content } => {
+            println!("📝 Writing file: {}", path);
+            ("write_file", file_manager.write_file(&PathBuf::from(path), content).await.map(|_| String::new()))
+        }
+        ToolCall::EditFile { path, instructions } => {
+            println!("✏️ Editing file: {}", path);
+            ("edit_file", file_manager.edit_file(ai_client, &PathBuf::from(path), instructions, false).await.map(|_| String::new()))
+        }
+        ToolCall::RunCommand { command } => {
+            println!("⚡ Running command: {}", command);
+            let outcome = match CommandSpec::parse(command) {
+                Ok(spec) => {
+                    terminal_manager
+                        .execute_spec_streaming(&spec, Some(COMMAND_TIMEOUT), CancellationToken::new(), |line| {
+                            match line {
+                                StreamLine::Stdout(l) => println!("{}", l),
+                                StreamLine::Stderr(l) => eprintln!("{}", l),
+                            }
+                        })
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            ("run_command", outcome)
+        }
+        ToolCall::ReadFile { path } => {
+            println!("📖 Reading file: {}", path);
+            ("read_file", file_manager.read_file(&PathBuf::from(path)).await)
+        }
+        ToolCall::DeleteFile { path } => {
+            println!("🗑️ Deleting file: {}", path);
+            ("delete_file", file_manager.delete_path(&PathBuf::from(path)).await.map(|_| String::new()))
+        }
+        ToolCall::CreateDirectory { path } => {
+            println!("📁 Creating directory: {}", path);
+            ("create_directory", fs::create_dir_all(path).await.map(|_| String::new()).map_err(anyhow::Error::from))
+        }
+        ToolCall::ListDirectory { path } => {
+            println!("📋 Listing directory: {}", path);
+            ("list_directory", file_manager.list_directory(&PathBuf::from(path), &file_ops::SearchOptions::default()).await.map(|items| items.join("\n")))
+        }
+        ToolCall::ApplyPlan { ops } => {
+            println!("🗂️ Applying {} file op(s)", ops.len());
+            ("apply_plan", file_manager.apply_plan(ai_client, ops.clone()).await.map(|_| String::new()))
+        }
+        ToolCall::SpawnBackground { command } => {
+            println!("🧵 Spawning background job: {}", command);
+            ("spawn_background", terminal_manager.spawn_background(command).await.map(|id| id.to_string()))
+        }
+        ToolCall::ListJobs => {
+            println!("📋 Listing background jobs");
+            let jobs = terminal_manager.list_jobs().await;
+            ("list_jobs", Ok(jobs.iter().map(format_job).collect::<Vec<_>>().join("\n")))
+        }
+        ToolCall::WaitJob { id } => {
+            println!("⏳ Waiting on job: {}", id);
+            ("wait_job", terminal_manager.wait_job(*id).await.map(|code| code.to_string()))
+        }
+        ToolCall::KillJob { id } => {
+            println!("🛑 Killing job: {}", id);
+            ("kill_job", terminal_manager.kill_job(*id).await.map(|_| String::new()))
+        }
+    };
+
+    match outcome {
+        Ok(output) => ToolResult {
+            id: envelope.id.clone(),
+            tool: tool.to_string(),
+            success: true,
+            output,
+            retryable: true,
+        },
+        Err(e) => {
+            let retryable = e
+                .downcast_ref::<AgentError>()
+                .map(AgentError::is_retryable)
+                .unwrap_or(true);
+            ToolResult {
+                id: envelope.id.clone(),
+                tool: tool.to_string(),
+                success: false,
+                output: e.to_string(),
+                retryable,
+            }
+        }
+    }
+}
+
 async fn interactive_mode(
     ai_client: &OpenRouterClient,
     file_manager: &FileManager,
     terminal_manager: &TerminalManager,
 ) -> Result<()> {
-    use dialoguer::Input;
-    
+    use rustyline::Editor;
+    use rustyline::history::DefaultHistory;
+
     println!("🤖 AI CLI Agent - Interactive Mode");
     println!("Type 'exit' to quit");
     println!("=================================");
-    
+
+    let mut session = ShellSession::new()?;
+    let mut editor: Editor<ShellCompleter, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellCompleter::new()));
+    let _ = editor.load_history(&session.history_path);
+
     loop {
-        let task: String = Input::new()
-            .with_prompt("Task")
-            .interact_text()?;
-        
-        if task.to_lowercase() == "exit" {
+        let line = match editor.readline("Task> ") {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(trimmed);
+        let _ = editor.save_history(&session.history_path);
+
+        if trimmed.eq_ignore_ascii_case("exit") {
             break;
         }
-        
+
+        let expanded = session.expand_aliases(trimmed);
+
+        if let Some(rest) = expanded.strip_prefix("alias ") {
+            match rest.split_once('=') {
+                Some((name, value)) => {
+                    session.set_alias(name.trim(), value.trim());
+                    if let Some(helper) = editor.helper_mut() {
+                        helper.aliases = session.aliases.clone();
+                    }
+                    println!("Alias set: {} = {}", name.trim(), value.trim());
+                }
+                None => eprintln!("❌ Usage: alias name=expansion"),
+            }
+            continue;
+        }
+
+        if let Some(rest) = expanded.strip_prefix("cd ") {
+            if let Err(e) = shell_session::change_directory(terminal_manager, &mut session, Path::new(rest.trim())).await {
+                eprintln!("❌ Error: {}", e);
+            }
+            continue;
+        }
+
+        if let Some(rest) = expanded.strip_prefix("export ") {
+            match rest.split_once('=') {
+                Some((name, value)) => {
+                    if let Err(e) = shell_session::set_environment_variable(terminal_manager, &mut session, name.trim(), value.trim()).await {
+                        eprintln!("❌ Error: {}", e);
+                    }
+                }
+                None => eprintln!("❌ Usage: export NAME=VALUE"),
+            }
+            continue;
+        }
+
         if let Err(e) = execute_task(
             ai_client,
             file_manager,
             terminal_manager,
             "You are an AI software development assistant. Execute the following task:",
-            &task,
+            &expanded,
         ).await {
             eprintln!("❌ Error: {}", e);
         }
     }
-    
+
     Ok(())
 }
 
-#[derive(Debug)]
-enum AIAction {
+/// A single structured action the model asked us to perform, tagged by
+/// `tool` with its arguments nested under `args`, e.g.
+/// `{"tool":"write_file","args":{"path":"...","content":"..."}}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "tool", content = "args", rename_all = "snake_case")]
+enum ToolCall {
     WriteFile { path: String, content: String },
     EditFile { path: String, instructions: String },
     RunCommand { command: String },
@@ -258,108 +748,77 @@ enum AIAction {
     DeleteFile { path: String },
     CreateDirectory { path: String },
     ListDirectory { path: String },
+    ApplyPlan { ops: Vec<file_ops::FileOp> },
+    SpawnBackground { command: String },
+    ListJobs,
+    WaitJob { id: u64 },
+    KillJob { id: u64 },
 }
 
-fn parse_ai_response(response: &str) -> Vec<AIAction> {
-    let mut actions = Vec::new();
-    
-    // Simple parsing - in a real implementation, this would be more sophisticated
-    let lines: Vec<&str> = response.lines().collect();
-    let mut current_action = String::new();
-    let mut current_type = None;
-    
-    for line in lines {
-        let trimmed = line.trim();
-        
-        if trimmed.starts_with("WRITE_FILE:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("write");
-            current_action = trimmed["WRITE_FILE:".len()..].trim().to_string();
-        } else if trimmed.starts_with("EDIT_FILE:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("edit");
-            current_action = trimmed["EDIT_FILE:".len()..].trim().to_string();
-        } else if trimmed.starts_with("RUN_COMMAND:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("run");
-            current_action = trimmed["RUN_COMMAND:".len()..].trim().to_string();
-        } else if trimmed.starts_with("READ_FILE:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("read");
-            current_action = trimmed["READ_FILE:".len()..].trim().to_string();
-        } else if trimmed.starts_with("DELETE_FILE:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("delete");
-            current_action = trimmed["DELETE_FILE:".len()..].trim().to_string();
-        } else if trimmed.starts_with("CREATE_DIRECTORY:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("create_dir");
-            current_action = trimmed["CREATE_DIRECTORY:".len()..].trim().to_string();
-        } else if trimmed.starts_with("LIST_DIRECTORY:") {
-            if let Some(action) = finalize_action(&current_action, &current_type) {
-                actions.push(action);
-            }
-            current_type = Some("list_dir");
-            current_action = trimmed["LIST_DIRECTORY:".len()..].trim().to_string();
-        } else if !trimmed.is_empty() {
-            current_action.push('\n');
-            current_action.push_str(trimmed);
-        }
-    }
-    
-    if let Some(action) = finalize_action(&current_action, &current_type) {
-        actions.push(action);
-    }
-    
-    actions
+/// A `ToolCall` plus the request id the model attached to it, so a result
+/// can be correlated back to the call that produced it.
+#[derive(Debug, Deserialize)]
+struct ToolCallEnvelope {
+    #[serde(default = "default_call_id")]
+    id: String,
+    #[serde(flatten)]
+    call: ToolCall,
+}
+
+fn default_call_id() -> String {
+    crate::utils::generate_uuid()
+}
+
+/// The outcome of dispatching a `ToolCallEnvelope`, fed back to the model
+/// as part of the next turn's context.
+#[derive(Debug, Serialize)]
+struct ToolResult {
+    id: String,
+    tool: String,
+    success: bool,
+    output: String,
+    /// Whether the failure is the kind the model could plausibly fix by
+    /// trying again (e.g. writing a missing file first). `false` for
+    /// errors like `AgentError::PermissionDenied` that retrying can't fix.
+    retryable: bool,
 }
 
-fn finalize_action(action: &str, action_type: &Option<&str>) -> Option<AIAction> {
-    let action_type = action_type?;
-    if action.is_empty() {
-        return None;
+/// Parses the model's response into a list of tool calls.
+///
+/// Accepts a bare JSON array of calls, NDJSON (one call per line), or the
+/// same wrapped in a fenced ```json code block, tolerating prose before or
+/// after the fence.
+fn parse_tool_calls(response: &str) -> Result<Vec<ToolCallEnvelope>> {
+    let payload = extract_json_block(response).unwrap_or(response.trim());
+
+    if payload.is_empty() {
+        return Ok(Vec::new());
     }
-    
-    let parts: Vec<&str> = action.splitn(2, '\n').collect();
-    let first_line = parts[0].trim();
-    let content = if parts.len() > 1 { parts[1].trim() } else { "" };
-    
-    match action_type {
-        "write" => Some(AIAction::WriteFile {
-            path: first_line.to_string(),
-            content: content.to_string(),
-        }),
-        "edit" => Some(AIAction::EditFile {
-            path: first_line.to_string(),
-            instructions: content.to_string(),
-        }),
-        "run" => Some(AIAction::RunCommand {
-            command: action.to_string(),
-        }),
-        "read" => Some(AIAction::ReadFile {
-            path: first_line.to_string(),
-        }),
-        "delete" => Some(AIAction::DeleteFile {
-            path: first_line.to_string(),
-        }),
-        "create_dir" => Some(AIAction::CreateDirectory {
-            path: first_line.to_string(),
-        }),
-        "list_dir" => Some(AIAction::ListDirectory {
-            path: first_line.to_string(),
-        }),
-        _ => None,
+
+    if let Ok(calls) = serde_json::from_str::<Vec<ToolCallEnvelope>>(payload) {
+        return Ok(calls);
+    }
+
+    // Fall back to NDJSON: one tool call object per non-empty line.
+    let mut calls = Vec::new();
+    for line in payload.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let call = serde_json::from_str::<ToolCallEnvelope>(line)
+            .map_err(|e| AgentError::ParseError(format!("line `{}`: {}", line, e)))?;
+        calls.push(call);
     }
+
+    Ok(calls)
+}
+
+/// Locates the first ```json fenced block in `response`, if any, and
+/// returns its inner contents.
+fn extract_json_block(response: &str) -> Option<&str> {
+    let fence_start = response.find("```json")?;
+    let body_start = fence_start + "```json".len();
+    let body_end = response[body_start..].find("```")?;
+    Some(response[body_start..body_start + body_end].trim())
 }
\ No newline at end of file
@@ -0,0 +1,246 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::ai::OpenRouterClient;
+use crate::tokenizer::ContextChunk;
+use crate::utils::is_text_file;
+
+const CHUNK_LINES: usize = 40;
+const CHUNK_OVERLAP: usize = 8;
+
+/// An embedded slice of a source file, ready to be ranked against a
+/// query vector or handed to `send_message_with_context`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    pub path: PathBuf,
+    pub line_range: (usize, usize),
+    pub vector: Vec<f32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexCache {
+    /// `"{len}:{modified_secs}"` per file, cheap enough to check on every
+    /// reindex without reading the file, same idea as the size check
+    /// `get_directory_size` does.
+    file_signatures: HashMap<PathBuf, String>,
+    chunks: Vec<IndexedChunk>,
+}
+
+/// A persisted semantic index over a work directory's text files, so the
+/// agent can pull relevant snippets for a prompt instead of requiring
+/// the caller to hand-pick context strings.
+pub struct SearchIndex {
+    index_dir: PathBuf,
+    cache: IndexCache,
+}
+
+impl SearchIndex {
+    fn cache_path(index_dir: &Path) -> PathBuf {
+        index_dir.join("semantic_index.json")
+    }
+
+    /// Loads the cache from `index_dir` if one already exists there,
+    /// otherwise starts empty.
+    pub async fn load(index_dir: PathBuf) -> Result<Self> {
+        let cache_path = Self::cache_path(&index_dir);
+        let cache = if cache_path.exists() {
+            let content = tokio::fs::read_to_string(&cache_path).await?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            IndexCache::default()
+        };
+
+        Ok(Self { index_dir, cache })
+    }
+
+    async fn save(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.index_dir).await?;
+        let content = serde_json::to_string_pretty(&self.cache)?;
+        tokio::fs::write(Self::cache_path(&self.index_dir), content).await?;
+        Ok(())
+    }
+
+    /// Walks `work_dir`, re-embedding only the text files whose
+    /// size/modified-time signature changed since the last run, and
+    /// drops chunks belonging to files that were removed or changed.
+    /// Persists the updated cache before returning.
+    pub async fn reindex(&mut self, client: &OpenRouterClient, work_dir: &Path) -> Result<()> {
+        let mut seen = HashSet::new();
+        let mut changed_files = Vec::new();
+
+        for entry in WalkDir::new(work_dir).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !is_text_file(name) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let signature = format!("{}:{}", meta.len(), modified);
+
+            seen.insert(path.to_path_buf());
+
+            if self.cache.file_signatures.get(path) == Some(&signature) {
+                continue;
+            }
+
+            let Ok(content) = tokio::fs::read_to_string(path).await else {
+                continue;
+            };
+
+            self.cache.file_signatures.insert(path.to_path_buf(), signature);
+            changed_files.push((path.to_path_buf(), content));
+        }
+
+        self.cache.file_signatures.retain(|path, _| seen.contains(path));
+        self.cache.chunks.retain(|chunk| {
+            seen.contains(&chunk.path) && !changed_files.iter().any(|(path, _)| path == &chunk.path)
+        });
+
+        let mut texts = Vec::new();
+        let mut locations = Vec::new();
+        for (path, content) in &changed_files {
+            for (start, end, text) in chunk_lines(content, CHUNK_LINES, CHUNK_OVERLAP) {
+                texts.push(text);
+                locations.push((path.clone(), (start, end)));
+            }
+        }
+
+        if !texts.is_empty() {
+            let vectors = client.embed(&texts).await?;
+            for (vector, (path, line_range)) in vectors.into_iter().zip(locations) {
+                self.cache.chunks.push(IndexedChunk { path, line_range, vector });
+            }
+        }
+
+        self.save().await
+    }
+
+    /// Embeds `prompt` and returns the `top_k` indexed chunks ranked by
+    /// cosine similarity, highest first.
+    pub async fn query(&self, client: &OpenRouterClient, prompt: &str, top_k: usize) -> Result<Vec<IndexedChunk>> {
+        let prompt_vector = client
+            .embed(&[prompt.to_string()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("embedding request returned no vectors"))?;
+
+        let mut scored: Vec<(f32, &IndexedChunk)> = self
+            .cache
+            .chunks
+            .iter()
+            .map(|chunk| (cosine_similarity(&prompt_vector, &chunk.vector), chunk))
+            .collect();
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        Ok(scored.into_iter().take(top_k).map(|(_, chunk)| chunk.clone()).collect())
+    }
+}
+
+/// Re-reads each chunk's line range off disk (the cache only stores
+/// `{path, line_range, vector}`, not the text itself) and turns the
+/// result into `ContextChunk`s for `fit_context`, prioritized by
+/// similarity rank (the caller already sorted `chunks` best-first via
+/// `query`). A chunk whose file disappeared, or shrank to end before the
+/// chunk's first line, is skipped rather than failing the whole batch; a
+/// chunk cut short by a shrunken file keeps the lines that remain.
+pub async fn to_context_chunks(chunks: &[IndexedChunk]) -> Result<Vec<ContextChunk>> {
+    let mut out = Vec::with_capacity(chunks.len());
+
+    for (rank, chunk) in chunks.iter().enumerate() {
+        let Ok(content) = tokio::fs::read_to_string(&chunk.path).await else {
+            continue;
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        let start = chunk.line_range.0.saturating_sub(1).min(lines.len());
+        let end = chunk.line_range.1.min(lines.len());
+        if start >= end {
+            continue;
+        }
+
+        let label = format!("{} L{}-{}", chunk.path.display(), chunk.line_range.0, chunk.line_range.1);
+        let snippet = lines[start..end].join("\n");
+        out.push(ContextChunk::new(
+            label.clone(),
+            format!("// {}\n{}", label, snippet),
+            (chunks.len() - rank) as u32,
+        ));
+    }
+
+    Ok(out)
+}
+
+fn chunk_lines(content: &str, window: usize, overlap: usize) -> Vec<(usize, usize, String)> {
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    let stride = window.saturating_sub(overlap).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < lines.len() {
+        let end = (start + window).min(lines.len());
+        chunks.push((start + 1, end, lines[start..end].join("\n")));
+        if end == lines.len() {
+            break;
+        }
+        start += stride;
+    }
+
+    chunks
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_ranks_closer_vectors_higher() {
+        let query = vec![1.0, 0.0];
+        let close = vec![0.9, 0.1];
+        let far = vec![0.0, 1.0];
+        assert!(cosine_similarity(&query, &close) > cosine_similarity(&query, &far));
+    }
+
+    #[test]
+    fn cosine_similarity_is_zero_for_a_zero_vector() {
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn cosine_similarity_is_one_for_identical_vectors() {
+        let v = vec![0.3, 0.4, 0.5];
+        assert!((cosine_similarity(&v, &v) - 1.0).abs() < 1e-6);
+    }
+}
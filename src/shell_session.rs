@@ -0,0 +1,172 @@
+use anyhow::Result;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::terminal::TerminalManager;
+
+/// Commands handled in-process by `TerminalManager::execute_builtin`,
+/// kept here too so the completer can suggest them without a dependency
+/// cycle back into `terminal`.
+const BUILTIN_COMMANDS: &[&str] = &[
+    "cd", "pwd", "echo", "cat", "ls", "mkdir", "rm", "cp", "mv", "which", "export",
+];
+
+/// Persistent state for an interactive session: the working directory and
+/// environment variables a user's `cd`/`export` have accumulated, a table
+/// of user-defined aliases, and a path to a history dotfile. Unlike the
+/// one-shot `Execute`/`Run` commands, this state survives across turns of
+/// `interactive_mode`.
+pub struct ShellSession {
+    pub work_dir: PathBuf,
+    pub env: HashMap<String, String>,
+    pub aliases: HashMap<String, String>,
+    pub history_path: PathBuf,
+}
+
+impl ShellSession {
+    pub fn new() -> Result<Self> {
+        let history_path = dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".ai_cli_agent_history");
+
+        Ok(Self {
+            work_dir: std::env::current_dir()?,
+            env: HashMap::new(),
+            aliases: HashMap::new(),
+            history_path,
+        })
+    }
+
+    /// Records that `name` now expands to `expansion` when it appears as
+    /// the first word of a command line.
+    pub fn set_alias(&mut self, name: &str, expansion: &str) {
+        self.aliases.insert(name.to_string(), expansion.to_string());
+    }
+
+    /// Expands a leading alias in `command_line`, if the first word names
+    /// one. Only the first word is considered, matching how shells expand
+    /// simple command aliases (not full alias chains).
+    pub fn expand_aliases(&self, command_line: &str) -> String {
+        let mut parts = command_line.splitn(2, char::is_whitespace);
+        let first = match parts.next() {
+            Some(first) => first,
+            None => return command_line.to_string(),
+        };
+
+        match self.aliases.get(first) {
+            Some(expansion) => match parts.next() {
+                Some(rest) => format!("{} {}", expansion, rest),
+                None => expansion.clone(),
+            },
+            None => command_line.to_string(),
+        }
+    }
+}
+
+/// A `rustyline` completer that suggests built-in command names, known
+/// aliases, and executables discovered on `$PATH` for the word ending at
+/// the cursor, wherever it falls on the line being edited.
+pub struct ShellCompleter {
+    pub aliases: HashMap<String, String>,
+}
+
+impl ShellCompleter {
+    pub fn new() -> Self {
+        Self {
+            aliases: HashMap::new(),
+        }
+    }
+
+    fn path_executables(&self, prefix: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        for dir in std::env::split_paths(&path_var) {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                if let Some(name) = entry.file_name().to_str() {
+                    if name.starts_with(prefix) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        names
+    }
+}
+
+impl Completer for ShellCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let prefix_start = line[..pos].rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let prefix = &line[prefix_start..pos];
+
+        let mut candidates: Vec<String> = BUILTIN_COMMANDS
+            .iter()
+            .map(|s| s.to_string())
+            .chain(self.aliases.keys().cloned())
+            .filter(|c| c.starts_with(prefix))
+            .collect();
+        candidates.extend(self.path_executables(prefix));
+        candidates.sort();
+        candidates.dedup();
+
+        let pairs = candidates
+            .into_iter()
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+
+        Ok((prefix_start, pairs))
+    }
+}
+
+impl Hinter for ShellCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for ShellCompleter {}
+
+impl Validator for ShellCompleter {}
+
+impl Helper for ShellCompleter {}
+
+/// Applies `TerminalManager::change_directory` and then mirrors the result
+/// into `session.work_dir` so the prompt and completion logic agree with
+/// the process's actual working directory.
+pub async fn change_directory(
+    terminal: &TerminalManager,
+    session: &mut ShellSession,
+    path: &std::path::Path,
+) -> Result<()> {
+    terminal.change_directory(path).await?;
+    session.work_dir = std::env::current_dir()?;
+    Ok(())
+}
+
+/// Applies `TerminalManager::set_environment_variable` and mirrors the
+/// result into `session.env`.
+pub async fn set_environment_variable(
+    terminal: &TerminalManager,
+    session: &mut ShellSession,
+    name: &str,
+    value: &str,
+) -> Result<()> {
+    terminal.set_environment_variable(name, value).await?;
+    session.env.insert(name.to_string(), value.to_string());
+    Ok(())
+}
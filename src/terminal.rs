@@ -1,12 +1,187 @@
 use anyhow::{Result, anyhow};
+use std::collections::HashMap;
 use std::process::{Command, Stdio};
-use tokio::process::Command as TokioCommand;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::{Child, Command as TokioCommand};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+use std::path::{Path, PathBuf};
 use which::which;
 
+use crate::errors::AgentError;
+
+/// The running/exited state of a background job, as last observed by
+/// `list_jobs` (a non-blocking poll, not a wait).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    Exited(i32),
+}
+
+/// A snapshot of a background job's identity and last-known status.
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub id: u64,
+    pub pid: u32,
+    pub command: String,
+    pub status: JobStatus,
+}
+
+struct Job {
+    pid: u32,
+    command: String,
+    child: Child,
+}
+
+/// Tracks every `Child` spawned via `spawn_background`, keyed by an
+/// incrementing job id, so the agent can list, wait on, or kill processes
+/// it started instead of leaking them once the spawning call returns.
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    jobs: HashMap<u64, Job>,
+}
+
+impl JobTable {
+    fn insert(&mut self, command: String, child: Child) -> Result<u64> {
+        let pid = child.id().ok_or_else(|| anyhow!("spawned process has no pid"))?;
+        self.next_id += 1;
+        let id = self.next_id;
+        self.jobs.insert(id, Job { pid, command, child });
+        Ok(id)
+    }
+
+    fn list(&mut self) -> Vec<JobInfo> {
+        let mut infos: Vec<JobInfo> = self
+            .jobs
+            .iter_mut()
+            .map(|(id, job)| {
+                let status = match job.child.try_wait() {
+                    Ok(Some(exit_status)) => JobStatus::Exited(exit_status.code().unwrap_or(-1)),
+                    _ => JobStatus::Running,
+                };
+                JobInfo {
+                    id: *id,
+                    pid: job.pid,
+                    command: job.command.clone(),
+                    status,
+                }
+            })
+            .collect();
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    /// Removes and returns the job so its owner can await its exit
+    /// without holding the table's lock for the duration of that wait.
+    fn take(&mut self, id: u64) -> Result<Job> {
+        self.jobs.remove(&id).ok_or_else(|| anyhow!("No such job: {}", id))
+    }
+}
+
+/// A line of output produced by a running command, tagged by the stream
+/// it came from so a caller can print stdout/stderr live as they arrive.
+#[derive(Debug, Clone)]
+pub enum StreamLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Non-timeout, non-exit-code failure modes specific to the streaming
+/// execution path. `CommandTimedOut` lives on `AgentError` instead, since
+/// the retry loop needs to reason about it alongside `CommandFailed`.
+#[derive(Debug, Error)]
+pub enum StreamedCommandError {
+    #[error("command was cancelled")]
+    Cancelled,
+}
+
+/// A program and its argument vector, built without ever going through a
+/// shell. Spawning a `CommandSpec` directly is immune to shell injection
+/// by construction: there is no `;`, `$(...)`, or unescaped quote for a
+/// shell to reinterpret, because no shell ever sees the string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CommandSpec {
+    /// Tokenizes a command line into a program and its arguments,
+    /// respecting single and double quotes the way a shell would, but
+    /// without invoking one. Does not perform glob expansion, variable
+    /// substitution, or any other shell feature by design.
+    pub fn parse(command_line: &str) -> Result<Self> {
+        let tokens = tokenize(command_line)?;
+        let mut iter = tokens.into_iter();
+        let program = iter
+            .next()
+            .ok_or_else(|| AgentError::ParseError("empty command".to_string()))?;
+        Ok(Self {
+            program,
+            args: iter.collect(),
+        })
+    }
+
+    fn to_tokio_command(&self) -> Result<TokioCommand> {
+        let resolved = which(&self.program)
+            .map_err(|e| anyhow!("Program not found: {} ({})", self.program, e))?;
+        let mut cmd = TokioCommand::new(resolved);
+        cmd.args(&self.args);
+        Ok(cmd)
+    }
+}
+
+fn tokenize(command_line: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    let mut chars = command_line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    in_token = true;
+                }
+                c if c.is_whitespace() => {
+                    if in_token {
+                        tokens.push(std::mem::take(&mut current));
+                        in_token = false;
+                    }
+                }
+                _ => {
+                    current.push(c);
+                    in_token = true;
+                }
+            },
+        }
+    }
+
+    if quote.is_some() {
+        return Err(AgentError::ParseError(format!(
+            "unterminated quote in command: {}",
+            command_line
+        ))
+        .into());
+    }
+
+    if in_token {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
 pub struct TerminalManager {
     shell: String,
+    jobs: Mutex<JobTable>,
 }
 
 impl TerminalManager {
@@ -18,11 +193,74 @@ impl TerminalManager {
                 "/bin/bash".to_string()
             }
         });
-        
-        Self { shell }
+
+        Self {
+            shell,
+            jobs: Mutex::new(JobTable::default()),
+        }
     }
-    
+
+    /// Spawns `command` in the background (no shell) and returns a job id
+    /// that `list_jobs`/`wait_job`/`kill_job` can use to track it. The
+    /// child is killed if it's ever dropped without being waited on, so
+    /// the agent can't orphan it by exiting early.
+    pub async fn spawn_background(&self, command: &str) -> Result<u64> {
+        let spec = CommandSpec::parse(command)?;
+        let mut cmd = spec.to_tokio_command()?;
+        cmd.current_dir(std::env::current_dir()?);
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+        cmd.stdin(Stdio::null());
+        cmd.kill_on_drop(true);
+
+        let child = cmd.spawn()?;
+        let mut jobs = self.jobs.lock().await;
+        jobs.insert(command.to_string(), child)
+    }
+
+    /// Returns a snapshot of every tracked job's id, pid, command, and
+    /// last-known running/exited status.
+    pub async fn list_jobs(&self) -> Vec<JobInfo> {
+        let mut jobs = self.jobs.lock().await;
+        jobs.list()
+    }
+
+    /// Blocks until job `id` exits and returns its exit code. Takes the
+    /// job out of the table first so other jobs can still be listed,
+    /// waited on, or killed while this one is in flight.
+    pub async fn wait_job(&self, id: u64) -> Result<i32> {
+        let mut job = {
+            let mut jobs = self.jobs.lock().await;
+            jobs.take(id)?
+        };
+        let status = job.child.wait().await?;
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    /// Signals job `id`'s stored child handle directly (no `taskkill`/`kill`
+    /// shell-out) and waits for it to exit. Same take-then-await shape as
+    /// `wait_job`, so killing one job doesn't block the table.
+    pub async fn kill_job(&self, id: u64) -> Result<()> {
+        let mut job = {
+            let mut jobs = self.jobs.lock().await;
+            jobs.take(id)?
+        };
+        job.child.start_kill()?;
+        job.child.wait().await?;
+        Ok(())
+    }
+
+    /// Parses `command` into a `CommandSpec` and runs it directly, with no
+    /// intervening shell. This is the default, injection-safe path.
     pub async fn execute_command(&self, command: &str) -> Result<String> {
+        let spec = CommandSpec::parse(command)?;
+        self.execute_spec(&spec).await
+    }
+
+    /// Runs `command` through the user's shell (`-c`/`cmd /C`). Only meant
+    /// to be reached when the caller explicitly opts into shell features
+    /// (pipes, globbing, `&&`) via `run --shell`.
+    pub async fn execute_command_in_shell(&self, command: &str) -> Result<String> {
         let mut cmd = if cfg!(target_os = "windows") {
             let mut cmd = TokioCommand::new("cmd");
             cmd.args(&["/C", command]);
@@ -32,44 +270,196 @@ impl TerminalManager {
             cmd.args(&["-c", command]);
             cmd
         };
-        
+
         cmd.current_dir(std::env::current_dir()?);
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
-        
+
+        self.run_to_completion(cmd).await
+    }
+
+    /// Runs an already-built `CommandSpec` with no shell involved. Curated
+    /// built-ins (`cd`, `pwd`, `echo`, ...) are handled in-process first so
+    /// their effects (and their behavior) don't depend on an external
+    /// shell being present or behaving consistently across platforms.
+    pub async fn execute_spec(&self, spec: &CommandSpec) -> Result<String> {
+        if let Some(result) = self.execute_builtin(spec) {
+            return result;
+        }
+
+        let mut cmd = spec.to_tokio_command()?;
+        cmd.current_dir(std::env::current_dir()?);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        self.run_to_completion(cmd).await
+    }
+
+    async fn run_to_completion(&self, mut cmd: TokioCommand) -> Result<String> {
         let mut child = cmd.spawn()?;
-        
+
+        let mut stdout_pipe = child.stdout.take();
+        let mut stderr_pipe = child.stderr.take();
+
         let mut stdout = Vec::new();
         let mut stderr = Vec::new();
-        
-        if let Some(mut stdout_pipe) = child.stdout.take() {
-            stdout_pipe.read_to_end(&mut stdout).await?;
-        }
-        
-        if let Some(mut stderr_pipe) = child.stderr.take() {
-            stderr_pipe.read_to_end(&mut stderr).await?;
-        }
-        
+
+        // Drain both pipes concurrently so a program that fills stderr
+        // while we're still waiting on stdout (or vice versa) can't
+        // deadlock us.
+        let stdout_read = async {
+            if let Some(pipe) = stdout_pipe.as_mut() {
+                pipe.read_to_end(&mut stdout).await
+            } else {
+                Ok(0)
+            }
+        };
+        let stderr_read = async {
+            if let Some(pipe) = stderr_pipe.as_mut() {
+                pipe.read_to_end(&mut stderr).await
+            } else {
+                Ok(0)
+            }
+        };
+        let (stdout_result, stderr_result) = tokio::join!(stdout_read, stderr_read);
+        stdout_result?;
+        stderr_result?;
+
         let status = child.wait().await?;
-        
+
         let stdout_str = String::from_utf8_lossy(&stdout);
         let stderr_str = String::from_utf8_lossy(&stderr);
-        
+
         if !status.success() {
-            return Err(anyhow!(
-                "Command failed with exit code: {}\nStdout: {}\nStderr: {}",
-                status.code().unwrap_or(-1),
-                stdout_str,
-                stderr_str
-            ));
+            return Err(AgentError::CommandFailed {
+                code: status.code().unwrap_or(-1),
+                stdout: stdout_str.to_string(),
+                stderr: stderr_str.to_string(),
+            }
+            .into());
         }
-        
+
         let mut output = stdout_str.to_string();
         if !stderr_str.is_empty() {
             output.push_str("\n");
             output.push_str(&stderr_str);
         }
-        
+
+        Ok(output)
+    }
+
+    /// Runs `spec` with no shell involved, streaming each output line to
+    /// `on_line` as it arrives instead of buffering silently until exit.
+    /// Kills the child and returns `CommandTimedOut` if `timeout` elapses,
+    /// or `Cancelled` if `cancel` fires first (e.g. the user hits Ctrl-C
+    /// in interactive mode).
+    pub async fn execute_spec_streaming(
+        &self,
+        spec: &CommandSpec,
+        timeout: Option<Duration>,
+        cancel: CancellationToken,
+        mut on_line: impl FnMut(StreamLine) + Send,
+    ) -> Result<String> {
+        if let Some(result) = self.execute_builtin(spec) {
+            if let Ok(output) = &result {
+                for line in output.lines() {
+                    on_line(StreamLine::Stdout(line.to_string()));
+                }
+            }
+            return result;
+        }
+
+        let mut cmd = spec.to_tokio_command()?;
+        cmd.current_dir(std::env::current_dir()?);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        let stdout = child.stdout.take().expect("stdout was piped");
+        let stderr = child.stderr.take().expect("stderr was piped");
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<StreamLine>();
+
+        let stdout_tx = tx.clone();
+        let stdout_task = tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stdout_tx.send(StreamLine::Stdout(line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stderr_tx = tx.clone();
+        let stderr_task = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stderr_tx.send(StreamLine::Stderr(line)).is_err() {
+                    break;
+                }
+            }
+        });
+        drop(tx);
+
+        let timeout_sleep = tokio::time::sleep(timeout.unwrap_or(Duration::from_secs(0)));
+        tokio::pin!(timeout_sleep);
+
+        let mut stdout_buf = String::new();
+        let mut stderr_buf = String::new();
+
+        loop {
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    let _ = child.start_kill();
+                    let _ = child.wait().await;
+                    stdout_task.abort();
+                    stderr_task.abort();
+                    return Err(StreamedCommandError::Cancelled.into());
+                }
+                _ = &mut timeout_sleep, if timeout.is_some() => {
+                    let _ = child.start_kill();
+                    let _ = child.wait().await;
+                    stdout_task.abort();
+                    stderr_task.abort();
+                    return Err(AgentError::CommandTimedOut(timeout.unwrap()).into());
+                }
+                line = rx.recv() => {
+                    match line {
+                        Some(StreamLine::Stdout(l)) => {
+                            stdout_buf.push_str(&l);
+                            stdout_buf.push('\n');
+                            on_line(StreamLine::Stdout(l));
+                        }
+                        Some(StreamLine::Stderr(l)) => {
+                            stderr_buf.push_str(&l);
+                            stderr_buf.push('\n');
+                            on_line(StreamLine::Stderr(l));
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        let _ = tokio::join!(stdout_task, stderr_task);
+        let status = child.wait().await?;
+
+        if !status.success() {
+            return Err(AgentError::CommandFailed {
+                code: status.code().unwrap_or(-1),
+                stdout: stdout_buf,
+                stderr: stderr_buf,
+            }
+            .into());
+        }
+
+        let mut output = stdout_buf;
+        if !stderr_buf.is_empty() {
+            output.push_str("\n");
+            output.push_str(&stderr_buf);
+        }
+
         Ok(output)
     }
     
@@ -90,36 +480,50 @@ impl TerminalManager {
         cmd.stderr(Stdio::piped());
         
         let mut child = cmd.spawn()?;
-        
+
         if let Some(mut stdin) = child.stdin.take() {
             stdin.write_all(input.as_bytes()).await?;
             stdin.flush().await?;
             drop(stdin);
         }
-        
+
+        let mut stdout_pipe = child.stdout.take();
+        let mut stderr_pipe = child.stderr.take();
+
         let mut stdout = Vec::new();
         let mut stderr = Vec::new();
-        
-        if let Some(mut stdout_pipe) = child.stdout.take() {
-            stdout_pipe.read_to_end(&mut stdout).await?;
-        }
-        
-        if let Some(mut stderr_pipe) = child.stderr.take() {
-            stderr_pipe.read_to_end(&mut stderr).await?;
-        }
-        
+
+        // Drain both pipes concurrently; see `run_to_completion` for why.
+        let stdout_read = async {
+            if let Some(pipe) = stdout_pipe.as_mut() {
+                pipe.read_to_end(&mut stdout).await
+            } else {
+                Ok(0)
+            }
+        };
+        let stderr_read = async {
+            if let Some(pipe) = stderr_pipe.as_mut() {
+                pipe.read_to_end(&mut stderr).await
+            } else {
+                Ok(0)
+            }
+        };
+        let (stdout_result, stderr_result) = tokio::join!(stdout_read, stderr_read);
+        stdout_result?;
+        stderr_result?;
+
         let status = child.wait().await?;
         
         let stdout_str = String::from_utf8_lossy(&stdout);
         let stderr_str = String::from_utf8_lossy(&stderr);
         
         if !status.success() {
-            return Err(anyhow!(
-                "Command failed with exit code: {}\nStdout: {}\nStderr: {}",
-                status.code().unwrap_or(-1),
-                stdout_str,
-                stderr_str
-            ));
+            return Err(AgentError::CommandFailed {
+                code: status.code().unwrap_or(-1),
+                stdout: stdout_str.to_string(),
+                stderr: stderr_str.to_string(),
+            }
+            .into());
         }
         
         let mut output = stdout_str.to_string();
@@ -206,7 +610,185 @@ impl TerminalManager {
         } else {
             "ps aux".to_string()
         };
-        
+
         self.execute_command(&command).await
     }
+
+    /// Dispatches `spec` to its in-process built-in implementation, if one
+    /// exists. Returns `None` for anything outside the curated set so the
+    /// caller falls through to spawning a real program.
+    fn execute_builtin(&self, spec: &CommandSpec) -> Option<Result<String>> {
+        let result = match spec.program.as_str() {
+            "cd" => self.builtin_cd(&spec.args),
+            "pwd" => self.builtin_pwd(),
+            "echo" => Ok(spec.args.join(" ")),
+            "cat" => self.builtin_cat(&spec.args),
+            "ls" => self.builtin_ls(&spec.args),
+            "mkdir" => self.builtin_mkdir(&spec.args),
+            "rm" => self.builtin_rm(&spec.args),
+            "cp" => self.builtin_cp(&spec.args),
+            "mv" => self.builtin_mv(&spec.args),
+            "which" => self.builtin_which(&spec.args),
+            "export" => self.builtin_export(&spec.args),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn builtin_cd(&self, args: &[String]) -> Result<String> {
+        let target = args.first().ok_or_else(|| anyhow!("cd: missing operand"))?;
+        std::env::set_current_dir(target)
+            .map_err(|e| anyhow!("cd: {}: {}", target, e))?;
+        Ok(String::new())
+    }
+
+    fn builtin_pwd(&self) -> Result<String> {
+        let cwd = std::env::current_dir()?;
+        Ok(cwd.display().to_string())
+    }
+
+    fn builtin_cat(&self, args: &[String]) -> Result<String> {
+        if args.is_empty() {
+            return Err(anyhow!("cat: missing operand"));
+        }
+        let mut output = String::new();
+        for path in args {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("cat: {}: {}", path, e))?;
+            output.push_str(&content);
+        }
+        Ok(output)
+    }
+
+    fn builtin_ls(&self, args: &[String]) -> Result<String> {
+        let target = args.first().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+        let mut names: Vec<String> = std::fs::read_dir(&target)
+            .map_err(|e| anyhow!("ls: {}: {}", target.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        Ok(names.join("\n"))
+    }
+
+    fn builtin_mkdir(&self, args: &[String]) -> Result<String> {
+        let (recursive, paths) = split_flag(args, "-p");
+        if paths.is_empty() {
+            return Err(anyhow!("mkdir: missing operand"));
+        }
+        for path in paths {
+            let result = if recursive {
+                std::fs::create_dir_all(&path)
+            } else {
+                std::fs::create_dir(&path)
+            };
+            result.map_err(|e| anyhow!("mkdir: {}: {}", path, e))?;
+        }
+        Ok(String::new())
+    }
+
+    fn builtin_rm(&self, args: &[String]) -> Result<String> {
+        let (recursive, rest) = split_flag(args, "-r");
+        let (_force, paths) = split_flag(&rest, "-f");
+        if paths.is_empty() {
+            return Err(anyhow!("rm: missing operand"));
+        }
+        for path in &paths {
+            let metadata = std::fs::symlink_metadata(path);
+            match metadata {
+                Ok(m) if m.is_dir() => {
+                    if !recursive {
+                        return Err(anyhow!("rm: {}: is a directory (use -r)", path));
+                    }
+                    std::fs::remove_dir_all(path).map_err(|e| anyhow!("rm: {}: {}", path, e))?;
+                }
+                Ok(_) => {
+                    std::fs::remove_file(path).map_err(|e| anyhow!("rm: {}: {}", path, e))?;
+                }
+                Err(e) => return Err(anyhow!("rm: {}: {}", path, e)),
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn builtin_cp(&self, args: &[String]) -> Result<String> {
+        if args.len() != 2 {
+            return Err(anyhow!("cp: usage: cp <src> <dst>"));
+        }
+        std::fs::copy(&args[0], &args[1])
+            .map_err(|e| anyhow!("cp: {} -> {}: {}", args[0], args[1], e))?;
+        Ok(String::new())
+    }
+
+    fn builtin_mv(&self, args: &[String]) -> Result<String> {
+        if args.len() != 2 {
+            return Err(anyhow!("mv: usage: mv <src> <dst>"));
+        }
+        std::fs::rename(&args[0], &args[1])
+            .map_err(|e| anyhow!("mv: {} -> {}: {}", args[0], args[1], e))?;
+        Ok(String::new())
+    }
+
+    fn builtin_which(&self, args: &[String]) -> Result<String> {
+        let program = args.first().ok_or_else(|| anyhow!("which: missing operand"))?;
+        which(program)
+            .map(|path| path.display().to_string())
+            .map_err(|e| anyhow!("which: {}: {}", program, e))
+    }
+
+    fn builtin_export(&self, args: &[String]) -> Result<String> {
+        let assignment = args.first().ok_or_else(|| anyhow!("export: missing operand"))?;
+        let (name, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow!("export: expected NAME=VALUE, got `{}`", assignment))?;
+        std::env::set_var(name, value);
+        Ok(String::new())
+    }
+}
+
+/// Splits a flag like `-p` out of `args`, returning whether it was present
+/// and the remaining positional arguments in order.
+fn split_flag(args: &[String], flag: &str) -> (bool, Vec<String>) {
+    let mut present = false;
+    let mut rest = Vec::with_capacity(args.len());
+    for arg in args {
+        if arg == flag {
+            present = true;
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    (present, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_respects_single_and_double_quotes() {
+        let tokens = tokenize("echo 'hello world' \"foo bar\"").unwrap();
+        assert_eq!(tokens, vec!["echo", "hello world", "foo bar"]);
+    }
+
+    #[test]
+    fn tokenize_rejects_unterminated_quote() {
+        assert!(tokenize("echo 'unterminated").is_err());
+    }
+
+    #[test]
+    fn command_spec_parse_splits_program_and_args() {
+        let spec = CommandSpec::parse("ls -la /tmp").unwrap();
+        assert_eq!(spec.program, "ls");
+        assert_eq!(spec.args, vec!["-la", "/tmp"]);
+    }
+
+    #[test]
+    fn command_spec_parse_treats_shell_metacharacters_as_literal_args() {
+        // `;` and `$(...)` are never special here - they're just tokens,
+        // since no shell ever parses this string.
+        let spec = CommandSpec::parse("echo '$(rm -rf /)' ; ls").unwrap();
+        assert_eq!(spec.program, "echo");
+        assert_eq!(spec.args, vec!["$(rm -rf /)", ";", "ls"]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use anyhow::{Result, anyhow};
+use tiktoken_rs::{get_bpe_from_model, CoreBPE};
+
+/// A candidate piece of context (typically a file snippet) that
+/// `fit_context` may include, truncate, or drop depending on how much
+/// budget is left. Higher `priority` chunks are packed first.
+pub struct ContextChunk {
+    pub label: String,
+    pub content: String,
+    pub priority: u32,
+}
+
+impl ContextChunk {
+    pub fn new(label: impl Into<String>, content: impl Into<String>, priority: u32) -> Self {
+        Self {
+            label: label.into(),
+            content: content.into(),
+            priority,
+        }
+    }
+}
+
+/// What `fit_context` actually fit into the budget, plus the token
+/// counts it took to decide, so a caller can report usage before
+/// spending an API call on a request that was going to be rejected.
+pub struct FitResult {
+    pub chunks: Vec<String>,
+    pub system_tokens: usize,
+    pub user_tokens: usize,
+    pub context_tokens: usize,
+    pub dropped: Vec<String>,
+}
+
+/// Resolves the BPE table for `model`, falling back to GPT-4's encoding
+/// for OpenRouter model ids `tiktoken-rs` doesn't recognize directly
+/// (e.g. `anthropic/claude-2`), since nearly every model served through
+/// OpenRouter tokenizes close enough to `cl100k_base` for budgeting
+/// purposes.
+pub fn resolve_encoding(model: &str) -> Result<CoreBPE> {
+    get_bpe_from_model(model)
+        .or_else(|_| get_bpe_from_model("gpt-4"))
+        .map_err(|e| anyhow!("failed to resolve tokenizer for model `{}`: {}", model, e))
+}
+
+pub fn count_tokens(bpe: &CoreBPE, text: &str) -> usize {
+    bpe.encode_with_special_tokens(text).len()
+}
+
+/// The model's total context window in tokens. `tiktoken-rs` only knows
+/// encodings, not window sizes, so this is a small lookup over the
+/// models `Config::get_models_list` offers, with a conservative default
+/// for anything else.
+pub fn context_window_for_model(model: &str) -> usize {
+    match model {
+        "openai/gpt-4-turbo" => 128_000,
+        "openai/gpt-4" => 8_192,
+        "openai/gpt-3.5-turbo" => 16_385,
+        "anthropic/claude-2" | "anthropic/claude-instant-1" => 100_000,
+        "mistralai/mixtral-8x7b-instruct" => 32_768,
+        _ => 8_192,
+    }
+}
+
+/// Greedily packs `candidate_chunks` (highest `priority` first) into
+/// whatever budget remains after `system_prompt`, `user_message`, and
+/// `reserved_for_completion` are accounted for out of `budget`. A chunk
+/// that doesn't fully fit is truncated to the remaining space rather
+/// than skipped outright, so a caller gets partial context instead of
+/// none; anything after that point is dropped entirely.
+pub fn fit_context(
+    bpe: &CoreBPE,
+    system_prompt: &str,
+    user_message: &str,
+    candidate_chunks: &[ContextChunk],
+    budget: usize,
+    reserved_for_completion: usize,
+) -> FitResult {
+    let system_tokens = count_tokens(bpe, system_prompt);
+    let user_tokens = count_tokens(bpe, user_message);
+    let mut remaining = budget
+        .saturating_sub(reserved_for_completion)
+        .saturating_sub(system_tokens)
+        .saturating_sub(user_tokens);
+
+    let mut ordered: Vec<&ContextChunk> = candidate_chunks.iter().collect();
+    ordered.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+    let mut chunks = Vec::new();
+    let mut dropped = Vec::new();
+    let mut context_tokens = 0;
+
+    for chunk in ordered {
+        if remaining == 0 {
+            dropped.push(chunk.label.clone());
+            continue;
+        }
+
+        let tokens = count_tokens(bpe, &chunk.content);
+        if tokens <= remaining {
+            remaining -= tokens;
+            context_tokens += tokens;
+            chunks.push(chunk.content.clone());
+        } else {
+            chunks.push(truncate_to_tokens(bpe, &chunk.content, remaining));
+            context_tokens += remaining;
+            dropped.push(format!("{} (truncated)", chunk.label));
+            remaining = 0;
+        }
+    }
+
+    FitResult {
+        chunks,
+        system_tokens,
+        user_tokens,
+        context_tokens,
+        dropped,
+    }
+}
+
+fn truncate_to_tokens(bpe: &CoreBPE, text: &str, max_tokens: usize) -> String {
+    let tokens = bpe.encode_with_special_tokens(text);
+    bpe.decode(tokens.into_iter().take(max_tokens).collect())
+        .unwrap_or_default()
+}
@@ -1,9 +1,14 @@
 use anyhow::{Result, anyhow};
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use tokio::fs;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+use crate::errors::AgentError;
+use crate::filesystem::FileSystem;
+
 pub fn get_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -15,6 +20,12 @@ pub fn generate_uuid() -> String {
     Uuid::new_v4().to_string()
 }
 
+/// Hashes file content so a caller can detect whether a file changed
+/// between the read it saw and a later guarded write.
+pub fn hash_content(content: &str) -> String {
+    blake3::hash(content.as_bytes()).to_hex().to_string()
+}
+
 pub fn sanitize_filename(filename: &str) -> String {
     filename
         .chars()
@@ -53,10 +64,10 @@ pub async fn create_temp_file(content: &str, extension: &str) -> Result<PathBuf>
     Ok(file_path)
 }
 
-pub async fn read_file_lines(path: &Path, max_lines: Option<usize>) -> Result<Vec<String>> {
-    let content = fs::read_to_string(path).await?;
+pub async fn read_file_lines(fs: &dyn FileSystem, path: &Path, max_lines: Option<usize>) -> Result<Vec<String>> {
+    let content = fs.read_to_string(path).await?;
     let lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-    
+
     if let Some(max) = max_lines {
         Ok(lines.into_iter().take(max).collect())
     } else {
@@ -64,34 +75,49 @@ pub async fn read_file_lines(path: &Path, max_lines: Option<usize>) -> Result<Ve
     }
 }
 
-pub async fn append_to_file(path: &Path, content: &str) -> Result<()> {
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)
-        .await?;
-    
-    use tokio::io::AsyncWriteExt;
-    file.write_all(content.as_bytes()).await?;
-    file.write_all(b"\n").await?;
-    
-    Ok(())
+pub async fn append_to_file(fs: &dyn FileSystem, path: &Path, content: &str) -> Result<()> {
+    let mut payload = content.as_bytes().to_vec();
+    payload.push(b'\n');
+    fs.append(path, &payload).await
 }
 
-pub async fn prepend_to_file(path: &Path, content: &str) -> Result<()> {
-    let existing_content = fs::read_to_string(path).await?;
+pub async fn prepend_to_file(fs: &dyn FileSystem, path: &Path, content: &str) -> Result<()> {
+    let existing_content = fs.read_to_string(path).await?;
     let new_content = format!("{}\n{}", content, existing_content);
-    fs::write(path, new_content).await?;
-    Ok(())
+    fs.write(path, new_content.as_bytes()).await
 }
 
-pub async fn insert_into_file(path: &Path, line_number: usize, content: &str) -> Result<()> {
-    let lines = read_file_lines(path, None).await?;
-    
+/// Re-reads `path` and, if `expected_hash` is given, compares it against
+/// the freshly computed hash before handing back the current lines —
+/// mirroring HTTP's If-Match semantics so a caller can tell the file
+/// moved out from under it instead of silently overwriting someone
+/// else's change.
+async fn read_guarded_lines(fs: &dyn FileSystem, path: &Path, expected_hash: Option<&str>) -> Result<Vec<String>> {
+    let current = fs.read_to_string(path).await?;
+
+    if let Some(expected) = expected_hash {
+        let actual = hash_content(&current);
+        if actual != expected {
+            return Err(AgentError::Conflict(path.to_path_buf()).into());
+        }
+    }
+
+    Ok(current.lines().map(|s| s.to_string()).collect())
+}
+
+pub async fn insert_into_file(
+    fs: &dyn FileSystem,
+    path: &Path,
+    line_number: usize,
+    content: &str,
+    expected_hash: Option<&str>,
+) -> Result<()> {
+    let lines = read_guarded_lines(fs, path, expected_hash).await?;
+
     if line_number > lines.len() {
         return Err(anyhow!("Line number {} exceeds file length {}", line_number, lines.len()));
     }
-    
+
     let mut new_lines = Vec::new();
     for (i, line) in lines.iter().enumerate() {
         if i == line_number {
@@ -99,44 +125,52 @@ pub async fn insert_into_file(path: &Path, line_number: usize, content: &str) ->
         }
         new_lines.push(line.clone());
     }
-    
+
     let new_content = new_lines.join("\n");
-    fs::write(path, new_content).await?;
-    Ok(())
+    fs.write_atomic(path, new_content.as_bytes()).await
 }
 
-pub async fn replace_line_in_file(path: &Path, line_number: usize, new_content: &str) -> Result<()> {
-    let lines = read_file_lines(path, None).await?;
-    
+pub async fn replace_line_in_file(
+    fs: &dyn FileSystem,
+    path: &Path,
+    line_number: usize,
+    new_content: &str,
+    expected_hash: Option<&str>,
+) -> Result<()> {
+    let lines = read_guarded_lines(fs, path, expected_hash).await?;
+
     if line_number >= lines.len() {
         return Err(anyhow!("Line number {} exceeds file length {}", line_number, lines.len()));
     }
-    
+
     let mut new_lines = lines.clone();
     new_lines[line_number] = new_content.to_string();
-    
+
     let new_content = new_lines.join("\n");
-    fs::write(path, new_content).await?;
-    Ok(())
+    fs.write_atomic(path, new_content.as_bytes()).await
 }
 
-pub async fn delete_line_from_file(path: &Path, line_number: usize) -> Result<()> {
-    let lines = read_file_lines(path, None).await?;
-    
+pub async fn delete_line_from_file(
+    fs: &dyn FileSystem,
+    path: &Path,
+    line_number: usize,
+    expected_hash: Option<&str>,
+) -> Result<()> {
+    let lines = read_guarded_lines(fs, path, expected_hash).await?;
+
     if line_number >= lines.len() {
         return Err(anyhow!("Line number {} exceeds file length {}", line_number, lines.len()));
     }
-    
+
     let new_lines: Vec<String> = lines
         .into_iter()
         .enumerate()
         .filter(|(i, _)| i != &line_number)
         .map(|(_, line)| line)
         .collect();
-    
+
     let new_content = new_lines.join("\n");
-    fs::write(path, new_content).await?;
-    Ok(())
+    fs.write_atomic(path, new_content.as_bytes()).await
 }
 
 pub fn format_file_size(size: u64) -> String {
@@ -173,36 +207,42 @@ pub fn format_duration(seconds: u64) -> String {
     }
 }
 
-pub async fn count_files_in_directory(path: &Path) -> Result<usize> {
-    let mut count = 0;
-    let mut entries = fs::read_dir(path).await?;
-    
-    while let Some(entry) = entries.next_entry().await? {
-        let metadata = entry.metadata().await?;
-        if metadata.is_file() {
-            count += 1;
-        } else if metadata.is_dir() {
-            count += count_files_in_directory(&entry.path()).await?;
+/// Recurses over `path` via `fs`. Boxed because an `async fn` can't call
+/// itself directly when it also needs to stay generic over `&dyn FileSystem`.
+pub fn count_files_in_directory<'a>(
+    fs: &'a dyn FileSystem,
+    path: &'a Path,
+) -> Pin<Box<dyn Future<Output = Result<usize>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut count = 0;
+        for entry in fs.read_dir(path).await? {
+            let entry_path = path.join(&entry.name);
+            if entry.is_dir {
+                count += count_files_in_directory(fs, &entry_path).await?;
+            } else {
+                count += 1;
+            }
         }
-    }
-    
-    Ok(count)
+        Ok(count)
+    })
 }
 
-pub async fn get_directory_size(path: &Path) -> Result<u64> {
-    let mut total_size = 0;
-    let mut entries = fs::read_dir(path).await?;
-    
-    while let Some(entry) = entries.next_entry().await? {
-        let metadata = entry.metadata().await?;
-        if metadata.is_file() {
-            total_size += metadata.len();
-        } else if metadata.is_dir() {
-            total_size += get_directory_size(&entry.path()).await?;
+pub fn get_directory_size<'a>(
+    fs: &'a dyn FileSystem,
+    path: &'a Path,
+) -> Pin<Box<dyn Future<Output = Result<u64>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut total_size = 0;
+        for entry in fs.read_dir(path).await? {
+            let entry_path = path.join(&entry.name);
+            if entry.is_dir {
+                total_size += get_directory_size(fs, &entry_path).await?;
+            } else {
+                total_size += fs.metadata(&entry_path).await?.len;
+            }
         }
-    }
-    
-    Ok(total_size)
+        Ok(total_size)
+    })
 }
 
 pub fn is_text_file(filename: &str) -> bool {
@@ -222,46 +262,51 @@ pub fn is_text_file(filename: &str) -> bool {
     text_extensions.contains(&extension)
 }
 
-pub async fn create_directory_if_not_exists(path: &Path) -> Result<()> {
-    if !path.exists() {
-        fs::create_dir_all(path).await?;
+pub async fn create_directory_if_not_exists(fs: &dyn FileSystem, path: &Path) -> Result<()> {
+    if !fs.exists(path).await {
+        fs.create_dir_all(path).await?;
     }
     Ok(())
 }
 
-pub async fn copy_directory_recursive(src: &Path, dst: &Path) -> Result<()> {
-    if !src.exists() {
-        return Err(anyhow!("Source directory does not exist: {}", src.display()));
-    }
-    
-    if !src.is_dir() {
-        return Err(anyhow!("Source is not a directory: {}", src.display()));
-    }
-    
-    create_directory_if_not_exists(dst).await?;
-    
-    let mut entries = fs::read_dir(src).await?;
-    
-    while let Some(entry) = entries.next_entry().await? {
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        
-        if entry.file_type().await?.is_dir() {
-            copy_directory_recursive(&src_path, &dst_path).await?;
-        } else {
-            fs::copy(&src_path, &dst_path).await?;
+pub fn copy_directory_recursive<'a>(
+    fs: &'a dyn FileSystem,
+    src: &'a Path,
+    dst: &'a Path,
+) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+        let metadata = fs
+            .metadata(src)
+            .await
+            .map_err(|_| anyhow!("Source directory does not exist: {}", src.display()))?;
+
+        if !metadata.is_dir {
+            return Err(anyhow!("Source is not a directory: {}", src.display()));
         }
-    }
-    
-    Ok(())
+
+        create_directory_if_not_exists(fs, dst).await?;
+
+        for entry in fs.read_dir(src).await? {
+            let src_path = src.join(&entry.name);
+            let dst_path = dst.join(&entry.name);
+
+            if entry.is_dir {
+                copy_directory_recursive(fs, &src_path, &dst_path).await?;
+            } else {
+                fs.copy(&src_path, &dst_path).await?;
+            }
+        }
+
+        Ok(())
+    })
 }
 
-pub async fn ensure_file_exists(path: &Path, default_content: &str) -> Result<()> {
-    if !path.exists() {
+pub async fn ensure_file_exists(fs: &dyn FileSystem, path: &Path, default_content: &str) -> Result<()> {
+    if !fs.exists(path).await {
         if let Some(parent) = path.parent() {
-            create_directory_if_not_exists(parent).await?;
+            create_directory_if_not_exists(fs, parent).await?;
         }
-        fs::write(path, default_content).await?;
+        fs.write(path, default_content.as_bytes()).await?;
     }
     Ok(())
 }
\ No newline at end of file